@@ -1,15 +1,22 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{
+    postgres::{PgHasArrayType, PgTypeInfo},
+    types::Json,
+    FromRow, PgPool,
+};
 
 pub mod middlewares;
+pub mod utils;
 
+// the REST api and the JWT claims have always used snake_case, don't rename the fields
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
-#[serde(rename_all = "camelCase")]
 pub struct User {
     pub id: i64,
     pub ws_id: i64,
+    // tokens issued by the chat_server before it shared this type don't have it
     #[sqlx(default)]
+    #[serde(default)]
     pub ws_name: String,
     pub fullname: String,
     pub email: String,
@@ -21,7 +28,6 @@ pub struct User {
 }
 
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
-#[serde(rename_all = "camelCase")]
 pub struct Workspace {
     pub id: i64,
     pub name: String,
@@ -35,4 +41,231 @@ pub struct ChatUser {
     pub id: i64,
     pub fullname: String,
     pub email: String,
+    pub presence: Presence,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "user_presence", rename_all = "snake_case")]
+#[serde(rename_all = "camelCase")]
+pub enum Presence {
+    Online,
+    Away,
+    #[default]
+    Offline,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Message {
+    pub id: i64,
+    pub chat_id: i64,
+    // none for system messages
+    pub sender_id: Option<i64>,
+    pub content: String,
+    pub files: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub edited: bool,
+    // set on tombstones of deleted messages
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    // the root of the thread this message replies to
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    #[serde(default)]
+    pub reply_count: i32,
+    #[serde(default)]
+    pub last_reply_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub pinned_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub pinned_by: Option<i64>,
+    #[serde(default)]
+    pub kind: MessageKind,
+    // what a system message is about, its content is the english fallback
+    #[serde(default)]
+    pub payload: Option<Json<SystemEvent>>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub reactions: Vec<ReactionCount>,
+}
+
+// how far a member has read a chat
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct ChatRead {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub last_read_message_id: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ChatRead {
+    /// Move the read position of the user forward, it never goes back. Returns None if the
+    /// message is not in the chat; the membership is up to the caller.
+    pub async fn mark(
+        pool: &PgPool,
+        chat_id: i64,
+        user_id: i64,
+        message_id: i64,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            INSERT INTO chat_reads (chat_id, user_id, last_read_message_id)
+            SELECT $1, $2, $3 WHERE EXISTS (SELECT 1 FROM messages WHERE id = $3 AND chat_id = $1)
+            ON CONFLICT (chat_id, user_id) DO UPDATE SET
+                last_read_message_id = GREATEST(chat_reads.last_read_message_id, EXCLUDED.last_read_message_id),
+                updated_at = CURRENT_TIMESTAMP
+            RETURNING chat_id, user_id, last_read_message_id, updated_at
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(message_id)
+        .fetch_optional(pool)
+        .await
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, sqlx::Type,
+)]
+#[sqlx(type_name = "mention_kind", rename_all = "snake_case")]
+#[serde(rename_all = "camelCase")]
+pub enum MentionKind {
+    // ordered by priority, a direct mention wins over @here and @channel
+    Channel,
+    Here,
+    User,
+}
+
+// mentions are saved in bulk, as an array of kinds
+impl PgHasArrayType for MentionKind {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_mention_kind")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "message_kind", rename_all = "snake_case")]
+#[serde(rename_all = "camelCase")]
+pub enum MessageKind {
+    #[default]
+    User,
+    // changes to the chat, posted into its timeline
+    System,
+}
+
+/// The payload of a system message, clients render and localize it from the ids.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SystemEvent {
+    ChatCreated {
+        actor_id: i64,
+    },
+    ChatRenamed {
+        actor_id: i64,
+        name: String,
+    },
+    TopicChanged {
+        actor_id: i64,
+        topic: Option<String>,
+    },
+    ChatArchived {
+        actor_id: i64,
+    },
+    ChatUnarchived {
+        actor_id: i64,
+    },
+    MembersAdded {
+        actor_id: i64,
+        user_ids: Vec<i64>,
+    },
+    MembersRemoved {
+        actor_id: i64,
+        user_ids: Vec<i64>,
+    },
+    MemberJoined {
+        user_id: i64,
+    },
+    MemberLeft {
+        user_id: i64,
+    },
+}
+
+impl SystemEvent {
+    /// The users the event refers to, the actor first.
+    pub fn user_ids(&self) -> Vec<i64> {
+        match self {
+            SystemEvent::ChatCreated { actor_id }
+            | SystemEvent::ChatRenamed { actor_id, .. }
+            | SystemEvent::TopicChanged { actor_id, .. }
+            | SystemEvent::ChatArchived { actor_id }
+            | SystemEvent::ChatUnarchived { actor_id } => vec![*actor_id],
+            SystemEvent::MembersAdded { actor_id, user_ids }
+            | SystemEvent::MembersRemoved { actor_id, user_ids } => std::iter::once(*actor_id)
+                .chain(user_ids.iter().copied())
+                .collect(),
+            SystemEvent::MemberJoined { user_id } | SystemEvent::MemberLeft { user_id } => {
+                vec![*user_id]
+            }
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, sqlx::Type,
+)]
+#[sqlx(type_name = "chat_role", rename_all = "snake_case")]
+#[serde(rename_all = "camelCase")]
+pub enum ChatRole {
+    // ordered by rank, highest first
+    Owner,
+    Admin,
+    Member,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: i64,
+    // whether the current user reacted with this emoji
+    pub me: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, sqlx::Type)]
+#[sqlx(type_name = "chat_type", rename_all = "snake_case")]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum ChatType {
+    #[serde(alias = "single", alias = "Single")]
+    Single,
+    #[serde(alias = "group", alias = "Group")]
+    Group,
+    #[serde(alias = "private_channel", alias = "privateChannel")]
+    PrivateChannel,
+    #[serde(alias = "public_channel", alias = "PublicChannel")]
+    PublicChannel,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Chat {
+    pub id: i64,
+    pub ws_id: i64,
+    pub name: String,
+    pub r#type: ChatType,
+    pub members: Vec<i64>,
+    pub created_at: DateTime<Utc>,
+    // archived chats are read-only
+    #[serde(default)]
+    pub archived_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    // url of an uploaded file
+    #[serde(default)]
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub created_by: Option<i64>,
+    #[serde(default)]
+    pub updated_at: DateTime<Utc>,
 }
@@ -0,0 +1,136 @@
+use std::fmt;
+
+use axum::{
+    extract::{FromRequestParts, Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::{
+    headers::{authorization::Bearer, Authorization},
+    TypedHeader,
+};
+use serde::Deserialize;
+
+use crate::User;
+
+pub trait TokenVerify {
+    type Error: fmt::Debug;
+    fn verify(&self, token: &str) -> Result<User, Self::Error>;
+}
+
+// browser EventSource can't set headers, so the token may also come from the query string
+#[derive(Debug, Deserialize)]
+struct TokenParams {
+    token: String,
+}
+
+pub async fn verify_token<T>(State(state): State<T>, req: Request, next: Next) -> Response
+where
+    T: TokenVerify + Clone + Send + Sync + 'static,
+{
+    let (mut parts, body) = req.into_parts();
+    let token =
+        match TypedHeader::<Authorization<Bearer>>::from_request_parts(&mut parts, &state).await {
+            Ok(TypedHeader(Authorization(bearer))) => bearer.token().to_string(),
+            Err(e) if e.is_missing() => {
+                match Query::<TokenParams>::from_request_parts(&mut parts, &state).await {
+                    Ok(Query(params)) => params.token,
+                    Err(e) => {
+                        let msg = format!("parse token from query error: {}", e);
+                        return (StatusCode::UNAUTHORIZED, msg).into_response();
+                    }
+                }
+            }
+            Err(e) => {
+                let msg = format!("parse authorization error: {}", e);
+                return (StatusCode::UNAUTHORIZED, msg).into_response();
+            }
+        };
+
+    let req = match state.verify(&token) {
+        Ok(user) => {
+            let mut req = Request::from_parts(parts, body);
+            req.extensions_mut().insert(user);
+            req
+        }
+        Err(e) => {
+            let msg = format!("verify token failed: {:?}", e);
+            return (StatusCode::FORBIDDEN, msg).into_response();
+        }
+    };
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use axum::{body::Body, middleware::from_fn_with_state, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::utils::{DecodingKey, EncodingKey};
+
+    #[derive(Clone)]
+    struct AppState(Arc<DecodingKey>);
+
+    impl TokenVerify for AppState {
+        type Error = jwt_simple::Error;
+        fn verify(&self, token: &str) -> Result<User, Self::Error> {
+            self.0.verify(token)
+        }
+    }
+
+    async fn handler() -> impl IntoResponse {
+        (StatusCode::OK, "ok")
+    }
+
+    #[tokio::test]
+    async fn test_verify_token_middleware() -> Result<()> {
+        let ek = EncodingKey::load(include_str!("../../fixture/private.pem"))?;
+        let dk = DecodingKey::load(include_str!("../../fixture/public.pem"))?;
+        let state = AppState(Arc::new(dk));
+
+        let user = User {
+            id: 1,
+            ws_id: 1,
+            ws_name: "acme".to_string(),
+            fullname: "Alice".to_string(),
+            email: "alice@acme.org".to_string(),
+            password_hash: None,
+            created_at: chrono::Utc::now(),
+        };
+        let token = ek.sign(user)?;
+
+        let app = Router::new()
+            .route("/", get(handler))
+            .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
+            .with_state(state);
+
+        // token in the authorization header
+        let req = Request::get("/")
+            .header("Authorization", format!("Bearer {}", token))
+            .body(Body::empty())?;
+        let res = app.clone().oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // token in the query string
+        let req = Request::get(format!("/?token={}", token)).body(Body::empty())?;
+        let res = app.clone().oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // no token
+        let req = Request::get("/").body(Body::empty())?;
+        let res = app.clone().oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // bad token
+        let req = Request::get("/?token=bad-token").body(Body::empty())?;
+        let res = app.oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        Ok(())
+    }
+}
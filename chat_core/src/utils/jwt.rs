@@ -3,7 +3,7 @@ use std::ops::Deref;
 use anyhow::Result;
 use jwt_simple::prelude::*;
 
-use crate::{error::AppError, User};
+use crate::User;
 
 const JWT_DURATION: u64 = 60 * 60 * 24 * 7;
 const JWT_ISS: &str = "chat_server";
@@ -14,26 +14,26 @@ pub struct DecodingKey(Ed25519PublicKey);
 
 #[allow(dead_code)]
 impl EncodingKey {
-    pub fn load(pem: &str) -> Result<Self, AppError> {
+    pub fn load(pem: &str) -> Result<Self, jwt_simple::Error> {
         Ok(Self(Ed25519KeyPair::from_pem(pem)?))
     }
 
-    pub fn sign(&self, user: impl Into<User>) -> Result<String, AppError> {
+    pub fn sign(&self, user: impl Into<User>) -> Result<String, jwt_simple::Error> {
         let u: User = user.into();
         let claims = Claims::with_custom_claims(u, Duration::from_secs(JWT_DURATION));
         let claims = claims.with_issuer(JWT_ISS).with_audience(JWT_AUD);
 
-        Ok(self.0.sign(claims)?)
+        self.0.sign(claims)
     }
 }
 
 #[allow(dead_code)]
 impl DecodingKey {
-    pub fn load(pem: &str) -> Result<Self, AppError> {
+    pub fn load(pem: &str) -> Result<Self, jwt_simple::Error> {
         Ok(Self(Ed25519PublicKey::from_pem(pem)?))
     }
 
-    pub fn verify(&self, token: &str) -> Result<User, AppError> {
+    pub fn verify(&self, token: &str) -> Result<User, jwt_simple::Error> {
         let options = jwt_simple::common::VerificationOptions {
             allowed_issuers: Some(HashSet::from([JWT_ISS.to_string()])),
             allowed_audiences: Some(HashSet::from([JWT_AUD.to_string()])),
@@ -73,6 +73,7 @@ mod tests {
         let user1 = User {
             id: 1,
             ws_id: 1,
+            ws_name: "acme".to_string(),
             fullname: "<NAME>".to_string(),
             email: "<EMAIL>".to_string(),
             password_hash: None,
@@ -84,4 +85,36 @@ mod tests {
         assert_eq!(u, user2);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_jwt_should_accept_tokens_issued_before_user_was_shared() -> Result<()> {
+        let encoding_key = EncodingKey::load(include_str!("../../fixture/private.pem"))?;
+        let decoding_key = DecodingKey::load(include_str!("../../fixture/public.pem"))?;
+
+        // the claims chat_server used to sign, snake_case and without ws_name
+        #[derive(Serialize, Deserialize)]
+        struct OldUser {
+            id: i64,
+            ws_id: i64,
+            fullname: String,
+            email: String,
+            created_at: chrono::DateTime<chrono::Utc>,
+        }
+        let old = OldUser {
+            id: 1,
+            ws_id: 2,
+            fullname: "<NAME>".to_string(),
+            email: "<EMAIL>".to_string(),
+            created_at: chrono::Utc::now(),
+        };
+        let claims = Claims::with_custom_claims(old, Duration::from_secs(JWT_DURATION))
+            .with_issuer(JWT_ISS)
+            .with_audience(JWT_AUD);
+        let token = encoding_key.deref().sign(claims)?;
+
+        let user = decoding_key.verify(&token)?;
+        assert_eq!((user.id, user.ws_id), (1, 2));
+        assert_eq!(user.ws_name, "");
+        Ok(())
+    }
 }
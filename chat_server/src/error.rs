@@ -41,6 +41,9 @@ pub enum AppError {
 
     #[error("{0}")]
     MessageError(String),
+
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
 }
 
 impl IntoResponse for AppError {
@@ -56,6 +59,7 @@ impl IntoResponse for AppError {
             AppError::NotFound(_) => StatusCode::NOT_FOUND,
             AppError::MessageError(_) => StatusCode::BAD_REQUEST,
             AppError::ChatError(_) => StatusCode::BAD_REQUEST,
+            AppError::PermissionDenied(_) => StatusCode::FORBIDDEN,
         };
 
         (status_code, format!("{:?}", self)).into_response()
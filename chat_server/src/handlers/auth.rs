@@ -1,7 +1,7 @@
 use crate::{
     error::{AppError, ErrorResponse},
     models::user::{CreateUser, SignInUser},
-    AppState, User,
+    AppState,
 };
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
@@ -0,0 +1,39 @@
+use crate::{
+    error::AppError,
+    models::{channel::ListChannels, Chat},
+    AppState, User,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+
+pub(crate) async fn list_channels_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(input): Query<ListChannels>,
+) -> Result<impl IntoResponse, AppError> {
+    let channels = state.list_channels(&user, input).await?;
+    Ok(Json(channels))
+}
+
+// not behind verify_chat, the user isn't a member yet
+pub(crate) async fn join_chat_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = state.join_chat(id, &user).await?;
+    Ok(Json(chat))
+}
+
+pub(crate) async fn leave_chat_handler(
+    Extension(user): Extension<User>,
+    Extension(chat): Extension<Chat>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.leave_chat(&chat, user.id as u64).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
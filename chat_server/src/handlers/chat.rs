@@ -1,13 +1,15 @@
 use crate::{
     error::AppError,
     models::{
-        chat::{CreateChat, UpdateChat},
-        ChatType,
+        chat::{CreateChat, ListChats, UpdateChat},
+        invite::CreateInvite,
+        role::{ChatAction, UpdateChatRole},
+        Chat, ChatType,
     },
     AppState, User,
 };
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Extension, Json,
@@ -16,8 +18,11 @@ use axum::{
 pub(crate) async fn list_chat_handler(
     Extension(user): Extension<User>,
     State(state): State<AppState>,
+    Query(input): Query<ListChats>,
 ) -> Result<impl IntoResponse, AppError> {
-    let chats = state.list_chats(user.id as u64, user.ws_id as u64).await?;
+    let chats = state
+        .list_chats(user.id as u64, user.ws_id as u64, input)
+        .await?;
     Ok((StatusCode::OK, Json(chats)))
 }
 
@@ -26,76 +31,171 @@ pub(crate) async fn create_chat_handler(
     State(state): State<AppState>,
     Json(input): Json<CreateChat>,
 ) -> Result<impl IntoResponse, AppError> {
-    let chat = state.create_chat(user.ws_id as u64, input).await?;
+    let chat = state
+        .create_chat(user.ws_id as u64, user.id as u64, input)
+        .await?;
     Ok((StatusCode::CREATED, Json(chat)))
 }
 
-pub(crate) async fn get_chat_handler(
+// returns the existing direct message of the two users if there is one
+pub(crate) async fn dm_handler(
+    Extension(user): Extension<User>,
     State(state): State<AppState>,
-    Path(id): Path<u64>,
+    Path(user_id): Path<u64>,
 ) -> Result<impl IntoResponse, AppError> {
-    let chat = state.get_chat_by_id(id).await?;
-    match chat {
-        Some(chat) => Ok((StatusCode::OK, Json(chat))),
-        None => Err(AppError::NotFound(format!("chat: {} not found", id))),
-    }
+    let chat = state.get_or_create_dm(&user, user_id).await?;
+    Ok(Json(chat))
+}
+
+pub(crate) async fn get_chat_handler(
+    Extension(chat): Extension<Chat>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok((StatusCode::OK, Json(chat)))
 }
 
 pub(crate) async fn update_chat_handler(
+    Extension(user): Extension<User>,
+    Extension(origin_chat): Extension<Chat>,
     State(state): State<AppState>,
     Path(id): Path<u64>,
     Json(input): Json<UpdateChat>,
 ) -> Result<impl IntoResponse, AppError> {
-    let chat = state.get_chat_by_id(id).await?;
-    if chat.is_none() {
-        return Err(AppError::NotFound("chat: {id} not found".to_string()));
-    }
-
-    let origin_chat = chat.unwrap();
+    state
+        .check_chat_update(&origin_chat, user.id as u64, &input)
+        .await?;
     let update_chat = input.clone();
 
-    let input_type = input.chat_type;
-    if input_type.is_some() {
-        let update_type = input_type.unwrap();
-        if origin_chat.r#type == ChatType::PrivateChannel && update_type == ChatType::PublicChannel
-        {
+    // direct messages are unique per pair of users, their members don't change
+    if origin_chat.r#type != ChatType::Single && input.chat_type == Some(ChatType::Single) {
+        return Err(AppError::ChatError(
+            "only direct messages are single chats".to_string(),
+        ));
+    }
+    if let (ChatType::Single, Some(members)) = (&origin_chat.r#type, &input.members) {
+        let mut members = members.clone();
+        members.sort_unstable();
+        members.dedup();
+        let mut origin_members = origin_chat.members.clone();
+        origin_members.sort_unstable();
+        if members != origin_members {
             return Err(AppError::ChatError(
-                "conn't convert private to public".to_string(),
+                "the members of a direct message can't change".to_string(),
             ));
         }
+    }
 
-        if origin_chat.r#type == ChatType::Group && update_type == ChatType::Single {
+    if let Some(update_type) = input.chat_type {
+        if origin_chat.r#type == ChatType::PrivateChannel && update_type == ChatType::PublicChannel
+        {
             return Err(AppError::ChatError(
-                "conn't convert group to single".to_string(),
+                "conn't convert private to public".to_string(),
             ));
         }
     }
 
-    state.validate_members(input.members.clone()).await?;
+    if let Some(members) = input.members.clone() {
+        state.validate_members(origin_chat.ws_id, members).await?;
+    }
 
     let chat = state
-        .update_chat_by_id(id, origin_chat, update_chat)
+        .update_chat_by_id(id, user.id as u64, origin_chat, update_chat)
         .await?;
     Ok((StatusCode::OK, Json(chat)))
 }
 
+pub(crate) async fn create_invite_handler(
+    Extension(user): Extension<User>,
+    Extension(chat): Extension<Chat>,
+    State(state): State<AppState>,
+    Json(input): Json<CreateInvite>,
+) -> Result<impl IntoResponse, AppError> {
+    let invite = state.create_invite(&chat, user.id as u64, input).await?;
+    Ok((StatusCode::CREATED, Json(invite)))
+}
+
+pub(crate) async fn list_invites_handler(
+    Extension(user): Extension<User>,
+    Extension(chat): Extension<Chat>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let invites = state.list_invites(&chat, user.id as u64).await?;
+    Ok(Json(invites))
+}
+
+pub(crate) async fn revoke_invite_handler(
+    Extension(user): Extension<User>,
+    Extension(chat): Extension<Chat>,
+    State(state): State<AppState>,
+    Path((_id, invite_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let invite = state
+        .revoke_invite(&chat, user.id as u64, invite_id)
+        .await?;
+    Ok(Json(invite))
+}
+
+// not behind verify_chat, the user isn't a member yet
+pub(crate) async fn redeem_invite_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = state.redeem_invite(&code, &user).await?;
+    Ok(Json(chat))
+}
+
+// chats are archived rather than deleted, their history stays
 pub(crate) async fn delete_chat_handler(
     Extension(user): Extension<User>,
     State(state): State<AppState>,
     Path(id): Path<u64>,
 ) -> Result<impl IntoResponse, AppError> {
-    let chat = state.get_chat_by_id(id).await?;
-    if chat.is_none() {
-        return Err(AppError::NotFound("chat: {id} not found".to_string()));
-    }
+    state
+        .check_chat_permission(id, user.id as u64, ChatAction::Archive)
+        .await?;
+    let chat = state.archive_chat(id, user.id as u64, true).await?;
+    Ok(Json(chat))
+}
 
-    let chat = chat.unwrap();
-    if chat.ws_id != user.ws_id {
-        return Err(AppError::ChatError(
-            "chat does not belong to you".to_string(),
-        ));
-    }
+pub(crate) async fn unarchive_chat_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .check_chat_permission(id, user.id as u64, ChatAction::Archive)
+        .await?;
+    let chat = state.archive_chat(id, user.id as u64, false).await?;
+    Ok(Json(chat))
+}
+
+// not behind verify_chat, the workspace owner needn't be a member
+pub(crate) async fn purge_chat_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    state.purge_chat(id, &user).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn list_chat_members_handler(
+    Extension(chat): Extension<Chat>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let members = state.list_chat_members(chat.id as u64).await?;
+    Ok(Json(members))
+}
 
-    state.delete_chat_by_id(id).await?;
-    Ok(())
+pub(crate) async fn update_chat_role_handler(
+    Extension(user): Extension<User>,
+    Extension(chat): Extension<Chat>,
+    State(state): State<AppState>,
+    Path((_id, member_id)): Path<(u64, u64)>,
+    Json(input): Json<UpdateChatRole>,
+) -> Result<impl IntoResponse, AppError> {
+    let member = state
+        .set_chat_role(&chat, user.id as u64, member_id, input.role)
+        .await?;
+    Ok(Json(member))
 }
@@ -0,0 +1,15 @@
+use crate::{error::AppError, models::message::ListMessages, AppState, User};
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+pub(crate) async fn list_mentions_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(input): Query<ListMessages>,
+) -> Result<impl IntoResponse, AppError> {
+    let mentions = state.list_mentions(user.id as u64, input).await?;
+    Ok(Json(mentions))
+}
@@ -1,10 +1,13 @@
 use crate::{
     error::AppError,
-    models::message::{CreateMessage, ListMessages},
+    models::{
+        message::{CreateMessage, EditMessage, ListMessages},
+        role::ChatAction,
+    },
     AppState, User,
 };
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Extension, Json,
@@ -21,10 +24,86 @@ pub(crate) async fn send_message_handler(
 }
 
 pub(crate) async fn list_messages_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Query(list_msg): Query<ListMessages>,
+) -> Result<impl IntoResponse, AppError> {
+    let msgs = state.list_messages(id, user.id as u64, list_msg).await?;
+    Ok(Json(msgs))
+}
+
+pub(crate) async fn edit_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+    Json(input): Json<EditMessage>,
+) -> Result<impl IntoResponse, AppError> {
+    let msg = state
+        .edit_message(id, msg_id, user.id as u64, &input)
+        .await?;
+    Ok(Json(msg))
+}
+
+pub(crate) async fn list_message_edits_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let edits = state.list_message_edits(id, msg_id, user.id as u64).await?;
+    Ok(Json(edits))
+}
+
+pub(crate) async fn delete_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    state.delete_message(id, msg_id, user.id as u64).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn list_replies_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+    Query(input): Query<ListMessages>,
+) -> Result<impl IntoResponse, AppError> {
+    let msgs = state
+        .list_replies(id, msg_id, user.id as u64, input)
+        .await?;
+    Ok(Json(msgs))
+}
+
+pub(crate) async fn pin_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .check_chat_permission(id, user.id as u64, ChatAction::Pin)
+        .await?;
+    let msg = state.pin_message(id, msg_id, user.id as u64, true).await?;
+    Ok(Json(msg))
+}
+
+pub(crate) async fn unpin_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .check_chat_permission(id, user.id as u64, ChatAction::Pin)
+        .await?;
+    state.pin_message(id, msg_id, user.id as u64, false).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn list_pins_handler(
+    Extension(user): Extension<User>,
     State(state): State<AppState>,
     Path(id): Path<u64>,
-    Json(list_msg): Json<ListMessages>,
 ) -> Result<impl IntoResponse, AppError> {
-    let msgs = state.list_messages(id, list_msg).await?;
+    let msgs = state.list_pinned_messages(id, user.id as u64).await?;
     Ok(Json(msgs))
 }
@@ -1,7 +1,12 @@
 mod auth;
+mod channel;
 mod chat;
 mod file;
+mod mention;
 mod message;
+mod reaction;
+mod read;
+mod search;
 mod workspace;
 
 use axum::response::IntoResponse;
@@ -9,12 +14,27 @@ use axum::response::IntoResponse;
 #[allow(unused_imports)]
 pub(crate) use auth::*;
 
+#[allow(unused_imports)]
+pub(crate) use channel::*;
+
 #[allow(unused_imports)]
 pub(crate) use chat::*;
 
+#[allow(unused_imports)]
+pub(crate) use mention::*;
+
 #[allow(unused_imports)]
 pub(crate) use message::*;
 
+#[allow(unused_imports)]
+pub(crate) use reaction::*;
+
+#[allow(unused_imports)]
+pub(crate) use read::*;
+
+#[allow(unused_imports)]
+pub(crate) use search::*;
+
 #[allow(unused_imports)]
 pub(crate) use workspace::*;
 
@@ -0,0 +1,30 @@
+use crate::{error::AppError, models::reaction::CreateReaction, AppState, User};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+
+pub(crate) async fn add_reaction_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+    Json(input): Json<CreateReaction>,
+) -> Result<impl IntoResponse, AppError> {
+    let reactions = state
+        .add_reaction(id, msg_id, user.id as u64, &input.emoji)
+        .await?;
+    Ok((StatusCode::CREATED, Json(reactions)))
+}
+
+pub(crate) async fn remove_reaction_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id, emoji)): Path<(u64, u64, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .remove_reaction(id, msg_id, user.id as u64, &emoji)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,27 @@
+use crate::{error::AppError, models::read::MarkRead, AppState, User};
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+pub(crate) async fn mark_read_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<MarkRead>,
+) -> Result<impl IntoResponse, AppError> {
+    let read = state
+        .mark_chat_read(id, user.id as u64, input.message_id)
+        .await?;
+    Ok(Json(read))
+}
+
+pub(crate) async fn list_read_receipts_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, msg_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let receipts = state.list_read_receipts(id, msg_id, user.id as u64).await?;
+    Ok(Json(receipts))
+}
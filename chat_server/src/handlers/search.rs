@@ -0,0 +1,15 @@
+use crate::{error::AppError, models::search::SearchMessages, AppState, User};
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+pub(crate) async fn search_messages_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(input): Query<SearchMessages>,
+) -> Result<impl IntoResponse, AppError> {
+    let ret = state.search_messages(&user, input).await?;
+    Ok(Json(ret))
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{MatchedPath, Path, Request, State},
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+
+use crate::{error::AppError, AppState, User};
+
+// what members can still do in an archived chat, besides reading it
+const ARCHIVED_CHAT_ACTIONS: [&str; 3] = ["unarchive", "read", "leave"];
+
+/// Only members of a chat can reach the `/chats/:id` routes, the loaded chat is
+/// passed on to the handler as an extension. Archived chats are read-only.
+pub(crate) async fn verify_chat(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(params): Path<HashMap<String, String>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let Some(chat_id) = params.get("id").and_then(|id| id.parse::<u64>().ok()) else {
+        return AppError::NotFound("chat not found".to_string()).into_response();
+    };
+
+    let chat = match state.get_chat_by_id(chat_id).await {
+        // chats of other workspaces don't exist for the user
+        Ok(Some(chat)) if chat.ws_id == user.ws_id => chat,
+        Ok(_) => return AppError::NotFound(format!("chat: {} not found", chat_id)).into_response(),
+        Err(e) => return e.into_response(),
+    };
+
+    if !chat.members.contains(&user.id) {
+        return AppError::PermissionDenied(format!("not a member of chat: {}", chat_id))
+            .into_response();
+    }
+
+    if chat.archived_at.is_some() && req.method() != Method::GET {
+        let action = req
+            .extensions()
+            .get::<MatchedPath>()
+            .and_then(|path| path.as_str().rsplit('/').next());
+        if !action.is_some_and(|action| ARCHIVED_CHAT_ACTIONS.contains(&action)) {
+            return AppError::PermissionDenied(format!("chat: {} is archived", chat_id))
+                .into_response();
+        }
+    }
+
+    req.extensions_mut().insert(chat);
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Chat;
+    use axum::{
+        body::Body, http::StatusCode, middleware::from_fn_with_state, routing::get, Router,
+    };
+    use chat_core::middlewares::jwt::verify_token;
+    use tower::ServiceExt;
+
+    async fn handler(Extension(chat): Extension<Chat>) -> impl IntoResponse {
+        (StatusCode::OK, chat.id.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_verify_chat_middleware() -> anyhow::Result<()> {
+        let app_state = AppState::new_for_test().await?;
+
+        let mut users = app_state.create_test_users(3).await?;
+        users.push(app_state.create_test_user("erin", "test-other").await?);
+        let chat = app_state
+            .create_test_chat("verify", &[&users[0], &users[1]])
+            .await?;
+
+        let app = Router::new()
+            .route("/chats/:id", get(handler))
+            .route_layer(from_fn_with_state(app_state.clone(), verify_chat))
+            .layer(from_fn_with_state(
+                app_state.clone(),
+                verify_token::<AppState>,
+            ))
+            .with_state(app_state.clone());
+
+        let cases = [
+            (&users[0], chat.id, StatusCode::OK),
+            (&users[1], chat.id, StatusCode::OK),
+            // same workspace but not a member
+            (&users[2], chat.id, StatusCode::FORBIDDEN),
+            // another workspace
+            (&users[3], chat.id, StatusCode::NOT_FOUND),
+            (&users[0], chat.id + 1000, StatusCode::NOT_FOUND),
+        ];
+        for (user, chat_id, status) in cases {
+            let token = app_state.ek.sign(user.clone())?;
+            let req = Request::get(format!("/chats/{}", chat_id))
+                .header("Authorization", format!("Bearer {}", token))
+                .body(Body::empty())?;
+            let res = app.clone().oneshot(req).await?;
+            assert_eq!(res.status(), status);
+        }
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
+}
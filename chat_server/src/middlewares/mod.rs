@@ -1,3 +1,5 @@
+mod chat;
+
 use axum::{middleware::from_fn, Router};
 use chat_core::middlewares::request_id;
 use tower::ServiceBuilder;
@@ -8,7 +10,7 @@ use tower_http::{
 };
 use tracing::Level;
 
-pub mod auth;
+pub(crate) use chat::verify_chat;
 
 pub fn set_layer(app: Router) -> Router {
     app.layer(
@@ -0,0 +1,196 @@
+use chat_core::SystemEvent;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use super::{
+    chat::{add_chat_members, remove_chat_members},
+    message::create_system_message,
+    Chat, ChatType, User,
+};
+use crate::{error::AppError, AppState};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListChannels {
+    // matched against the channel name, case insensitive
+    pub q: Option<String>,
+}
+
+// a public channel in the channel directory of a workspace
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct ChannelInfo {
+    pub id: i64,
+    pub name: String,
+    pub member_count: i64,
+    // whether the user is a member already
+    pub joined: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AppState {
+    /// Public channels of the workspace of the user that can be joined, the biggest first.
+    pub async fn list_channels(
+        &self,
+        user: &User,
+        input: ListChannels,
+    ) -> Result<Vec<ChannelInfo>, AppError> {
+        let q = input.q.map(|q| q.trim().to_lowercase()).unwrap_or_default();
+
+        let channels = sqlx::query_as(
+            r#"
+            SELECT chats.id, chats.name, chats.created_at,
+                (SELECT count(*) FROM chat_members WHERE chat_members.chat_id = chats.id) AS member_count,
+                EXISTS(SELECT 1 FROM chat_members
+                    WHERE chat_members.chat_id = chats.id AND chat_members.user_id = $2
+                ) AS joined
+            FROM chats
+            WHERE chats.ws_id = $1 AND chats.type = 'public_channel' AND chats.archived_at IS NULL
+                AND strpos(lower(chats.name), $3) > 0
+            ORDER BY member_count DESC, chats.name
+            "#,
+        )
+        .bind(user.ws_id)
+        .bind(user.id)
+        .bind(q)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(channels)
+    }
+
+    /// Anyone in the workspace can join a public channel, joining twice is a no-op.
+    pub async fn join_chat(&self, chat_id: u64, user: &User) -> Result<Chat, AppError> {
+        let chat = match self.get_chat_by_id(chat_id).await? {
+            Some(chat) if chat.ws_id == user.ws_id => chat,
+            _ => return Err(AppError::NotFound(format!("chat: {} not found", chat_id))),
+        };
+        if chat.r#type != ChatType::PublicChannel {
+            return Err(AppError::PermissionDenied(
+                "only public channels can be joined".to_string(),
+            ));
+        }
+        if chat.archived_at.is_some() {
+            return Err(AppError::PermissionDenied(format!(
+                "chat: {} is archived",
+                chat_id
+            )));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let added = add_chat_members(&mut tx, chat.id, &[user.id]).await?;
+        if !added.is_empty() {
+            let event = SystemEvent::MemberJoined { user_id: user.id };
+            create_system_message(&mut tx, chat.id, event).await?;
+        }
+        tx.commit().await?;
+
+        match self.get_chat_by_id(chat_id).await? {
+            Some(chat) => Ok(chat),
+            None => Err(AppError::NotFound(format!("chat: {} not found", chat_id))),
+        }
+    }
+
+    /// Members leave channels and groups, a direct message always has both of its members.
+    pub async fn leave_chat(&self, chat: &Chat, user_id: u64) -> Result<(), AppError> {
+        if chat.r#type == ChatType::Single {
+            return Err(AppError::ChatError(
+                "can't leave a direct message".to_string(),
+            ));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        remove_chat_members(&mut tx, chat.id, &[user_id as i64]).await?;
+        let event = SystemEvent::MemberLeft {
+            user_id: user_id as i64,
+        };
+        create_system_message(&mut tx, chat.id, event).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::chat::CreateChat;
+    use chat_core::ChatRole;
+
+    #[tokio::test]
+    async fn test_join_leave_and_browse_channels() -> anyhow::Result<()> {
+        let app_state = AppState::new_for_test().await?;
+
+        let users = app_state.create_test_users(3).await?;
+        let (alice, bob, carol) = (&users[0], &users[1], &users[2]);
+
+        let mut chats = vec![];
+        for (name, public) in [("General", true), ("random", true), ("secret", false)] {
+            let chat = app_state
+                .create_chat(
+                    alice.ws_id as u64,
+                    alice.id as u64,
+                    CreateChat {
+                        name: Some(name.to_string()),
+                        public,
+                        members: vec![alice.id, bob.id],
+                    },
+                )
+                .await?;
+            chats.push(chat);
+        }
+
+        let channels = app_state
+            .list_channels(carol, ListChannels::default())
+            .await?;
+        let names: Vec<&str> = channels.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["General", "random"]);
+        assert!(channels.iter().all(|c| c.member_count == 2 && !c.joined));
+
+        let chat = app_state.join_chat(chats[0].id as u64, carol).await?;
+        assert_eq!(chat.members.len(), 3);
+        // a private channel needs an invite
+        assert!(app_state
+            .join_chat(chats[2].id as u64, carol)
+            .await
+            .is_err());
+
+        let channels = app_state
+            .list_channels(
+                carol,
+                ListChannels {
+                    q: Some("gen".to_string()),
+                },
+            )
+            .await?;
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].member_count, 3);
+        assert!(channels[0].joined);
+
+        // archived channels can't be joined, they aren't listed either
+        app_state
+            .archive_chat(chats[1].id as u64, alice.id as u64, true)
+            .await?;
+        let channels = app_state
+            .list_channels(carol, ListChannels::default())
+            .await?;
+        let names: Vec<&str> = channels.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["General"]);
+
+        // the owner leaves, the longest standing member takes over
+        app_state.leave_chat(&chat, alice.id as u64).await?;
+        assert!(
+            !app_state
+                .is_chat_member(chat.id as u64, alice.id as u64)
+                .await?
+        );
+        assert_eq!(
+            app_state
+                .get_chat_role(chat.id as u64, bob.id as u64)
+                .await?,
+            ChatRole::Owner
+        );
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
+}
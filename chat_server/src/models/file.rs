@@ -16,6 +16,32 @@ impl ChatFile {
         }
     }
 
+    /// The file behind an url returned by the upload, `/files/{ws_id}/{hash path}`.
+    pub fn from_url(url: &str) -> Option<(i64, Self)> {
+        let path = url.strip_prefix("/files/")?;
+        let (ws_id, path) = path.split_once('/')?;
+        let ws_id = ws_id.parse().ok()?;
+
+        let mut parts = path.splitn(3, '/');
+        let (part1, part2, name) = (parts.next()?, parts.next()?, parts.next()?);
+        let (part3, ext) = name.split_once('.')?;
+        let hash = format!("{}{}{}", part1, part2, part3);
+
+        let valid = part1.len() == 3
+            && part2.len() == 3
+            && hash.len() == 40
+            && hash.chars().all(|c| c.is_ascii_hexdigit())
+            && !ext.is_empty()
+            && !ext.contains('/');
+        valid.then(|| {
+            let file = Self {
+                ext: ext.to_string(),
+                hash,
+            };
+            (ws_id, file)
+        })
+    }
+
     pub fn url(&self, ws_id: String) -> String {
         format!("/files/{}/{}", ws_id, self.hash_to_path())
     }
@@ -33,6 +59,7 @@ impl ChatFile {
 
 #[cfg(test)]
 mod tests {
+    use super::ChatFile;
 
     // cargo test --package chat_server --lib -- models::file::tests::test_find_ext --exact --show-output
     #[test]
@@ -42,4 +69,25 @@ mod tests {
         println!("{:?}", ext);
         assert_eq!(ext, "png");
     }
+
+    #[test]
+    fn test_from_url_should_only_accept_upload_urls() {
+        let file = ChatFile::new("avatar.png", b"hello");
+        let url = file.url("1".to_string());
+        let (ws_id, parsed) = ChatFile::from_url(&url).expect("expect an upload url");
+        assert_eq!(ws_id, 1);
+        assert_eq!(parsed.hash_to_path(), file.hash_to_path());
+
+        for url in [
+            "../../etc/passwd",
+            "/files/1/../../etc/passwd",
+            "/files/1/aaf/4c6/../../../../etc/passwd",
+            "/files/x/aaf/4c6/1ddcc5e8a2dabede0f3b482cd9aea9434d.png",
+            "/files/1/aaf/4c6/1ddcc5e8a2dabede0f3b482cd9aea9434d",
+            "/files/1/aaf/4c6/1ddcc5e8a2dabede0f3b482cd9aea9434d./x",
+            "/tmp/chat/1/aaf/4c6/1ddcc5e8a2dabede0f3b482cd9aea9434d.png",
+        ] {
+            assert!(ChatFile::from_url(url).is_none(), "{}", url);
+        }
+    }
 }
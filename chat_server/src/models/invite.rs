@@ -0,0 +1,261 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chat_core::SystemEvent;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use super::{
+    chat::add_chat_members, message::create_system_message, role::ChatAction, Chat, ChatType, User,
+};
+use crate::{error::AppError, AppState};
+
+const INVITE_CODE_BYTES: usize = 16;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateInvite {
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_uses: Option<i32>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct ChatInvite {
+    pub id: i64,
+    pub chat_id: i64,
+    pub code: String,
+    pub created_by: i64,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AppState {
+    /// Public channels can be joined without an invite.
+    pub async fn create_invite(
+        &self,
+        chat: &Chat,
+        user_id: u64,
+        input: CreateInvite,
+    ) -> Result<ChatInvite, AppError> {
+        self.check_chat_permission(chat.id as u64, user_id, ChatAction::Invite)
+            .await?;
+
+        if !matches!(chat.r#type, ChatType::PrivateChannel | ChatType::Group) {
+            return Err(AppError::ChatError(
+                "invites are only available for private channels and groups".to_string(),
+            ));
+        }
+        if input.max_uses.is_some_and(|n| n <= 0) {
+            return Err(AppError::ChatError("max_uses must be positive".to_string()));
+        }
+        if input.expires_at.is_some_and(|t| t <= Utc::now()) {
+            return Err(AppError::ChatError(
+                "expires_at must be in the future".to_string(),
+            ));
+        }
+
+        let mut code = [0u8; INVITE_CODE_BYTES];
+        OsRng.fill_bytes(&mut code);
+
+        let invite = sqlx::query_as(
+            r#"
+            INSERT INTO chat_invites (chat_id, code, created_by, expires_at, max_uses)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *
+            "#,
+        )
+        .bind(chat.id)
+        .bind(hex::encode(code))
+        .bind(user_id as i64)
+        .bind(input.expires_at)
+        .bind(input.max_uses)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(invite)
+    }
+
+    /// Invites of the chat, the latest first, including the expired and revoked ones.
+    pub async fn list_invites(
+        &self,
+        chat: &Chat,
+        user_id: u64,
+    ) -> Result<Vec<ChatInvite>, AppError> {
+        self.check_chat_permission(chat.id as u64, user_id, ChatAction::Invite)
+            .await?;
+
+        let invites =
+            sqlx::query_as("SELECT * FROM chat_invites WHERE chat_id = $1 ORDER BY id DESC")
+                .bind(chat.id)
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(invites)
+    }
+
+    pub async fn revoke_invite(
+        &self,
+        chat: &Chat,
+        user_id: u64,
+        invite_id: u64,
+    ) -> Result<ChatInvite, AppError> {
+        self.check_chat_permission(chat.id as u64, user_id, ChatAction::Invite)
+            .await?;
+
+        let invite: Option<ChatInvite> = sqlx::query_as(
+            r#"
+            UPDATE chat_invites SET revoked_at = COALESCE(revoked_at, CURRENT_TIMESTAMP)
+            WHERE id = $1 AND chat_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(invite_id as i64)
+        .bind(chat.id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match invite {
+            Some(invite) => Ok(invite),
+            None => Err(AppError::NotFound(format!("invite {}", invite_id))),
+        }
+    }
+
+    /// Join the chat of the invite, members redeeming it again don't use it up.
+    pub async fn redeem_invite(&self, code: &str, user: &User) -> Result<Chat, AppError> {
+        let invite: Option<(i64, i64, bool)> = sqlx::query_as(
+            r#"
+            SELECT chats.id, chats.ws_id, chats.archived_at IS NOT NULL FROM chat_invites
+            JOIN chats ON chats.id = chat_invites.chat_id
+            WHERE chat_invites.code = $1
+            "#,
+        )
+        .bind(code)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let chat_id = match invite {
+            Some((chat_id, ws_id, archived)) if ws_id == user.ws_id => {
+                if archived {
+                    return Err(AppError::PermissionDenied(format!(
+                        "chat: {} is archived",
+                        chat_id
+                    )));
+                }
+                chat_id as u64
+            }
+            Some(_) => {
+                return Err(AppError::PermissionDenied(
+                    "the invite is for another workspace".to_string(),
+                ))
+            }
+            None => return Err(AppError::NotFound(format!("invite {}", code))),
+        };
+
+        let mut tx = self.pool.begin().await?;
+        let added = add_chat_members(&mut tx, chat_id as i64, &[user.id]).await?;
+        if !added.is_empty() {
+            // checked and used up in one go, concurrent redeems can't exceed max_uses; an
+            // error rolls the membership back
+            let ret = sqlx::query(
+                r#"
+                UPDATE chat_invites SET uses = uses + 1
+                WHERE code = $1 AND revoked_at IS NULL
+                    AND (expires_at IS NULL OR expires_at > CURRENT_TIMESTAMP)
+                    AND (max_uses IS NULL OR uses < max_uses)
+                "#,
+            )
+            .bind(code)
+            .execute(&mut *tx)
+            .await?;
+            if ret.rows_affected() == 0 {
+                return Err(AppError::NotFound(format!(
+                    "invite {} is revoked, expired or used up",
+                    code
+                )));
+            }
+
+            let event = SystemEvent::MemberJoined { user_id: user.id };
+            create_system_message(&mut tx, chat_id as i64, event).await?;
+        }
+        tx.commit().await?;
+
+        match self.get_chat_by_id(chat_id).await? {
+            Some(chat) => Ok(chat),
+            None => Err(AppError::NotFound(format!("chat: {} not found", chat_id))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_invites_should_be_limited_and_revocable() -> anyhow::Result<()> {
+        let app_state = AppState::new_for_test().await?;
+
+        let users = app_state.create_test_users(3).await?;
+        let (alice, bob, carol) = (&users[0], &users[1], &users[2]);
+        let dave = &app_state.create_test_user("dave", "test-other").await?;
+        let chat = app_state.create_test_chat("invites", &[alice]).await?;
+
+        let invite = app_state
+            .create_invite(
+                &chat,
+                alice.id as u64,
+                CreateInvite {
+                    expires_at: None,
+                    max_uses: Some(1),
+                },
+            )
+            .await?;
+
+        assert!(matches!(
+            app_state.redeem_invite(&invite.code, dave).await,
+            Err(AppError::PermissionDenied(_))
+        ));
+
+        let joined = app_state.redeem_invite(&invite.code, bob).await?;
+        assert_eq!(joined.members, vec![alice.id, bob.id]);
+        let system_messages =
+            "SELECT count(*) FROM messages WHERE chat_id = $1 AND kind = 'system'";
+        let (joins,): (i64,) = sqlx::query_as(system_messages)
+            .bind(joined.id)
+            .fetch_one(&app_state.pool)
+            .await?;
+        // redeeming again as a member doesn't count, nor joins again
+        app_state.redeem_invite(&invite.code, bob).await?;
+        let (rejoins,): (i64,) = sqlx::query_as(system_messages)
+            .bind(joined.id)
+            .fetch_one(&app_state.pool)
+            .await?;
+        assert_eq!(rejoins, joins);
+        assert!(app_state.redeem_invite(&invite.code, carol).await.is_err());
+        let chat = app_state.get_chat_by_id(joined.id as u64).await?;
+        assert_eq!(chat.map(|chat| chat.members), Some(vec![alice.id, bob.id]));
+
+        // only the owner manages invites
+        assert!(app_state
+            .create_invite(&joined, bob.id as u64, CreateInvite::default())
+            .await
+            .is_err());
+
+        let invite = app_state
+            .create_invite(&joined, alice.id as u64, CreateInvite::default())
+            .await?;
+        app_state
+            .revoke_invite(&joined, alice.id as u64, invite.id as u64)
+            .await?;
+        assert!(app_state.redeem_invite(&invite.code, carol).await.is_err());
+
+        let invites = app_state.list_invites(&joined, alice.id as u64).await?;
+        assert_eq!(invites.len(), 2);
+        assert!(invites[0].revoked_at.is_some());
+        assert_eq!(invites[1].uses, 1);
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
+}
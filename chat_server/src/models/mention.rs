@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+
+use chat_core::{ChatUser, MentionKind, Presence};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection};
+
+use super::{message::ListMessages, Message};
+use crate::{error::AppError, AppState};
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct MentionedMessage {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub message: Message,
+    // messages have a kind of their own
+    pub mention_kind: MentionKind,
+}
+
+impl AppState {
+    /// Messages mentioning the user in the chats they're still a member of, newest first.
+    pub async fn list_mentions(
+        &self,
+        user_id: u64,
+        input: ListMessages,
+    ) -> Result<Vec<MentionedMessage>, AppError> {
+        if input.after.is_some() || input.around.is_some() {
+            return Err(AppError::MessageError(
+                "mentions can only be listed before a message".to_string(),
+            ));
+        }
+
+        let mentions = sqlx::query_as(
+            r#"
+            SELECT messages.*, message_mentions.kind AS mention_kind FROM message_mentions
+            JOIN messages ON messages.id = message_mentions.message_id
+            JOIN chat_members ON chat_members.chat_id = messages.chat_id AND chat_members.user_id = $1
+            WHERE message_mentions.user_id = $1 AND messages.deleted_at IS NULL AND messages.id < $2
+            ORDER BY messages.id DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(user_id as i64)
+        .bind(input.before.unwrap_or(i64::MAX))
+        .bind(input.limit())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(mentions)
+    }
+}
+
+/// Resolve the mentions of the message against the members of its chat.
+pub(crate) async fn save_mentions(conn: &mut PgConnection, msg: &Message) -> Result<(), AppError> {
+    let names: HashSet<String> = parse_mentions(&msg.content).into_iter().collect();
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let members: Vec<ChatUser> = sqlx::query_as(
+        r#"
+        SELECT users.id, users.fullname, users.email, users.presence FROM chat_members
+        JOIN users ON users.id = chat_members.user_id
+        WHERE chat_members.chat_id = $1
+        "#,
+    )
+    .bind(msg.chat_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let channel = names.contains("channel");
+    let here = names.contains("here");
+    let (mut user_ids, mut kinds) = (vec![], vec![]);
+    for member in &members {
+        if Some(member.id) == msg.sender_id {
+            continue;
+        }
+        // a direct mention wins over @here and @channel
+        let kind = if is_mention_of(member, &names) {
+            MentionKind::User
+        } else if here && member.presence == Presence::Online {
+            MentionKind::Here
+        } else if channel {
+            MentionKind::Channel
+        } else {
+            continue;
+        };
+        user_ids.push(member.id);
+        kinds.push(kind);
+    }
+    if user_ids.is_empty() {
+        return Ok(());
+    }
+
+    // one statement however many members @channel reaches
+    sqlx::query(
+        r#"
+        INSERT INTO message_mentions (message_id, user_id, kind)
+        SELECT $1, unnest($2::bigint[]), unnest($3::mention_kind[])
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(msg.id)
+    .bind(&user_ids)
+    .bind(&kinds)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// `@alice` mentions alice@acme.com, or a member named "Alice" or "Alice Smith" as `@alicesmith`
+fn is_mention_of(user: &ChatUser, names: &HashSet<String>) -> bool {
+    let local = user.email.split('@').next().unwrap_or_default();
+    let fullname: String = user.fullname.split_whitespace().collect();
+    names.contains(&local.to_lowercase()) || names.contains(&fullname.to_lowercase())
+}
+
+// lowercased names after `@`, an `@` inside a word (like an email) is not a mention
+fn parse_mentions(content: &str) -> Vec<String> {
+    let mut names = vec![];
+    let mut prev = ' ';
+    for (i, c) in content.char_indices() {
+        if c == '@' && !prev.is_alphanumeric() {
+            let name: String = content[i + 1..]
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-'))
+                .collect();
+            let name = name.trim_end_matches('.');
+            if !name.is_empty() {
+                names.push(name.to_lowercase());
+            }
+        }
+        prev = c;
+    }
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{chat::UpdateChat, message::CreateMessage};
+
+    #[test]
+    fn test_parse_mentions() {
+        let names = parse_mentions("@Alice ask @bob.li, and @here. mail tom@acme.com or @");
+        assert_eq!(names, vec!["alice", "bob.li", "here"]);
+    }
+
+    #[test]
+    fn test_is_mention_of() {
+        let user = ChatUser {
+            id: 1,
+            fullname: "Alice Smith".to_string(),
+            email: "alice.s@acme.com".to_string(),
+            presence: Presence::Offline,
+        };
+        let names = |name: &str| HashSet::from([name.to_string()]);
+        assert!(is_mention_of(&user, &names("alice.s")));
+        assert!(is_mention_of(&user, &names("alicesmith")));
+        assert!(!is_mention_of(&user, &names("alice")));
+    }
+
+    #[tokio::test]
+    async fn test_mentions_should_follow_membership() -> anyhow::Result<()> {
+        let (app_state, users, chat) = AppState::new_for_test_with_chat(2).await?;
+        let (alice, bob) = (users[0].id as u64, users[1].id as u64);
+
+        app_state
+            .create_message(
+                chat.id as u64,
+                alice,
+                &CreateMessage {
+                    content: "hi @bob".to_string(),
+                    files: vec![],
+                    parent_id: None,
+                },
+            )
+            .await?;
+        let mentions = app_state
+            .list_mentions(bob, ListMessages::default())
+            .await?;
+        assert_eq!(mentions.len(), 1);
+        assert_eq!(mentions[0].mention_kind, MentionKind::User);
+
+        // removed from the chat, bob doesn't read it anymore
+        let input = UpdateChat {
+            members: Some(vec![alice as i64]),
+            ..Default::default()
+        };
+        app_state
+            .update_chat_by_id(chat.id as u64, alice, chat, input)
+            .await?;
+        let mentions = app_state
+            .list_mentions(bob, ListMessages::default())
+            .await?;
+        assert!(mentions.is_empty());
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
+}
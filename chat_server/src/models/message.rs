@@ -1,19 +1,71 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
-use super::Message;
+use super::{file::ChatFile, mention::save_mentions, Message};
 use crate::{error::AppError, AppState};
+use chat_core::{MessageKind, SystemEvent};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow, PgConnection};
+use tracing::warn;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct CreateMessage {
     pub content: String,
     pub files: Vec<String>,
+    #[serde(default)]
+    pub parent_id: Option<i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EditMessage {
+    pub content: String,
+}
+
+// a previous version of a message
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct MessageEdit {
+    pub id: i64,
+    pub message_id: i64,
+    pub content: String,
+    pub edited_at: DateTime<Utc>,
+}
+
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 100;
+
+const MESSAGE_COLUMNS: &str = "id, chat_id, sender_id, content, files, created_at, updated_at, edited, deleted_at, parent_id, reply_count, last_reply_at, pinned_at, pinned_by, kind, payload";
+
+/// A page of messages `before` or `after` a message id, or `around` it for permalinks.
+/// Without any of them, the latest messages.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ListMessages {
-    pub last_id: Option<u64>,
-    pub limit: u64,
+    pub before: Option<i64>,
+    pub after: Option<i64>,
+    pub around: Option<i64>,
+    pub limit: Option<u64>,
+}
+
+impl ListMessages {
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE) as i64
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MessagePage {
+    // newest first
+    pub messages: Vec<Message>,
+    // pass as `before` to get the older page
+    pub next_cursor: Option<i64>,
+    // pass as `after` to get the newer page
+    pub prev_cursor: Option<i64>,
+    // whether there are more messages in the direction of the request
+    pub has_more: bool,
 }
 
 impl AppState {
@@ -27,53 +79,802 @@ impl AppState {
             return Err(AppError::MessageError("content is empty".to_string()));
         }
 
-        let mut files = vec![];
+        if !msg.files.is_empty() {
+            self.verify_files(send_id, &msg.files).await?;
+        }
 
-        let base_dir = self.config.server.base_dir.clone();
+        let mut tx = self.pool.begin().await?;
 
-        for file in &msg.files {
-            let file_path = format!("{}/{}", base_dir, file);
-            let p = PathBuf::from(&file_path);
-            if !p.exists() {
-                return Err(AppError::MessageError("file is not exists".to_string()));
-            }
+        if let Some(parent_id) = msg.parent_id {
+            // lock the root, its reply summary is updated below
+            let parent: Option<Message> = sqlx::query_as(
+                "SELECT * FROM messages WHERE id = $1 AND chat_id = $2 AND deleted_at IS NULL FOR UPDATE",
+            )
+            .bind(parent_id)
+            .bind(chat_id as i64)
+            .fetch_optional(&mut *tx)
+            .await?;
 
-            files.push(file_path);
+            match parent {
+                None => return Err(AppError::NotFound(format!("message {}", parent_id))),
+                Some(parent) if parent.parent_id.is_some() => {
+                    return Err(AppError::MessageError(
+                        "can not reply to a reply".to_string(),
+                    ))
+                }
+                _ => {}
+            }
         }
 
-        let message = sqlx::query_as(
-            "INSERT INTO messages (chat_id, sender_id, content, files) VALUES ($1, $2, $3, $4) RETURNING *",
+        let message: Message = sqlx::query_as(
+            "INSERT INTO messages (chat_id, sender_id, content, files, parent_id) VALUES ($1, $2, $3, $4, $5) RETURNING *",
         )
         .bind(chat_id as i64)
         .bind(send_id as i64)
         .bind(msg.content.clone())
-        .bind(files)
-        .fetch_one(&self.pool)
+        .bind(&msg.files)
+        .bind(msg.parent_id)
+        .fetch_one(&mut *tx)
         .await?;
 
+        if let Some(parent_id) = msg.parent_id {
+            sqlx::query(
+                "UPDATE messages SET reply_count = reply_count + 1, last_reply_at = $1 WHERE id = $2",
+            )
+            .bind(message.created_at)
+            .bind(parent_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        save_mentions(&mut tx, &message).await?;
+
+        tx.commit().await?;
         Ok(message)
     }
 
+    /// Messages of the chat timeline, replies are listed by their thread.
     pub async fn list_messages(
         &self,
         chat_id: u64,
+        user_id: u64,
+        input: ListMessages,
+    ) -> Result<MessagePage, AppError> {
+        self.list_thread_messages(chat_id, None, user_id, input)
+            .await
+    }
+
+    pub async fn list_replies(
+        &self,
+        chat_id: u64,
+        msg_id: u64,
+        user_id: u64,
         input: ListMessages,
+    ) -> Result<MessagePage, AppError> {
+        let root: Option<(i64,)> =
+            sqlx::query_as("SELECT id FROM messages WHERE id = $1 AND chat_id = $2")
+                .bind(msg_id as i64)
+                .bind(chat_id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+        if root.is_none() {
+            return Err(AppError::NotFound(format!("message {}", msg_id)));
+        }
+
+        self.list_thread_messages(chat_id, Some(msg_id), user_id, input)
+            .await
+    }
+
+    // parent_id None lists the roots of the chat
+    async fn list_thread_messages(
+        &self,
+        chat_id: u64,
+        parent_id: Option<u64>,
+        user_id: u64,
+        input: ListMessages,
+    ) -> Result<MessagePage, AppError> {
+        let chat_id = chat_id as i64;
+        let parent_id = parent_id.map(|id| id as i64);
+        let limit = input.limit();
+
+        let mut messages = match (input.before, input.after, input.around) {
+            (before, None, None) => {
+                let before = before.unwrap_or(i64::MAX);
+                self.fetch_messages(chat_id, parent_id, "<", before, limit)
+                    .await?
+            }
+            (None, Some(after), None) => {
+                self.fetch_messages(chat_id, parent_id, ">", after, limit)
+                    .await?
+            }
+            (None, None, Some(around)) => {
+                // the message itself is in the older half
+                let mut messages = self
+                    .fetch_messages(chat_id, parent_id, ">", around, limit / 2)
+                    .await?;
+                let older = self
+                    .fetch_messages(chat_id, parent_id, "<=", around, limit - limit / 2)
+                    .await?;
+                messages.extend(older);
+                messages
+            }
+            _ => {
+                return Err(AppError::MessageError(
+                    "only one of before, after and around can be set".to_string(),
+                ))
+            }
+        };
+
+        let (has_older, has_newer) = match (messages.last(), messages.first()) {
+            (Some(oldest), Some(newest)) => {
+                sqlx::query_as(
+                    r#"
+                    SELECT
+                        EXISTS(SELECT 1 FROM messages WHERE chat_id = $1 AND parent_id IS NOT DISTINCT FROM $2 AND id < $3),
+                        EXISTS(SELECT 1 FROM messages WHERE chat_id = $1 AND parent_id IS NOT DISTINCT FROM $2 AND id > $4)
+                    "#,
+                )
+                .bind(chat_id)
+                .bind(parent_id)
+                .bind(oldest.id)
+                .bind(newest.id)
+                .fetch_one(&self.pool)
+                .await?
+            }
+            _ => (false, false),
+        };
+
+        let has_more = match (input.after, input.around) {
+            (Some(_), _) => has_newer,
+            (_, Some(_)) => has_older || has_newer,
+            _ => has_older,
+        };
+        let next_cursor = has_older.then(|| messages.last().map(|m| m.id)).flatten();
+        let prev_cursor = has_newer.then(|| messages.first().map(|m| m.id)).flatten();
+
+        self.attach_reactions(&mut messages, user_id).await?;
+        Ok(MessagePage {
+            messages,
+            next_cursor,
+            prev_cursor,
+            has_more,
+        })
+    }
+
+    // the messages next to the cursor in the direction of `op`, newest first
+    async fn fetch_messages(
+        &self,
+        chat_id: i64,
+        parent_id: Option<i64>,
+        op: &str,
+        cursor: i64,
+        limit: i64,
     ) -> Result<Vec<Message>, AppError> {
-        let last_id = input.last_id.unwrap_or(i64::MAX as _);
-        let limit = match input.limit {
-            0 => i64::MAX,
-            1..=100 => input.limit as i64,
-            _ => 100,
+        let order = if op.starts_with('<') { "DESC" } else { "ASC" };
+        let sql = format!(
+            "SELECT {} FROM messages WHERE chat_id = $1 AND parent_id IS NOT DISTINCT FROM $2 AND id {} $3 ORDER BY id {} LIMIT $4",
+            MESSAGE_COLUMNS, op, order
+        );
+
+        let mut messages: Vec<Message> = sqlx::query_as(&sql)
+            .bind(chat_id)
+            .bind(parent_id)
+            .bind(cursor)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        if order == "ASC" {
+            messages.reverse();
+        }
+        Ok(messages)
+    }
+
+    /// Only the sender can edit a message, the previous content goes to message_edits.
+    pub async fn edit_message(
+        &self,
+        chat_id: u64,
+        msg_id: u64,
+        user_id: u64,
+        input: &EditMessage,
+    ) -> Result<Message, AppError> {
+        if input.content.is_empty() {
+            return Err(AppError::MessageError("content is empty".to_string()));
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        let origin: Option<Message> =
+            sqlx::query_as("SELECT * FROM messages WHERE id = $1 AND chat_id = $2 AND deleted_at IS NULL FOR UPDATE")
+                .bind(msg_id as i64)
+                .bind(chat_id as i64)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+        let origin = match origin {
+            Some(msg) => msg,
+            None => return Err(AppError::NotFound(format!("message {}", msg_id))),
         };
 
-        let messages = sqlx::query_as(
-            "SELECT id, chat_id, sender_id, content, files, created_at FROM messages WHERE chat_id=$1 AND id < $2 ORDER BY id DESC LIMIT $3",
+        if origin.kind == MessageKind::System {
+            return Err(AppError::MessageError(
+                "system messages can't be edited".to_string(),
+            ));
+        }
+        if origin.sender_id != Some(user_id as i64) {
+            return Err(AppError::PermissionDenied(
+                "only the sender can edit a message".to_string(),
+            ));
+        }
+
+        sqlx::query("INSERT INTO message_edits (message_id, content) VALUES ($1, $2)")
+            .bind(origin.id)
+            .bind(origin.content)
+            .execute(&mut *tx)
+            .await?;
+
+        let message: Message = sqlx::query_as(
+            "UPDATE messages SET content = $1, edited = true, updated_at = CURRENT_TIMESTAMP WHERE id = $2 RETURNING *",
+        )
+        .bind(&input.content)
+        .bind(origin.id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // only the members mentioned for the first time are notified
+        save_mentions(&mut tx, &message).await?;
+
+        tx.commit().await?;
+        Ok(message)
+    }
+
+    /// Previous versions of a message, oldest first, visible to the workspace owner.
+    pub async fn list_message_edits(
+        &self,
+        chat_id: u64,
+        msg_id: u64,
+        user_id: u64,
+    ) -> Result<Vec<MessageEdit>, AppError> {
+        if self.workspace_owner_of_message(chat_id, msg_id).await? != user_id as i64 {
+            return Err(AppError::PermissionDenied(
+                "only the workspace owner can see message edits".to_string(),
+            ));
+        }
+
+        let edits = sqlx::query_as(
+            "SELECT id, message_id, content, edited_at FROM message_edits WHERE message_id = $1 ORDER BY id",
         )
+        .bind(msg_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(edits)
+    }
+
+    /// Leave a tombstone of the message, the sender or the workspace owner can delete it.
+    pub async fn delete_message(
+        &self,
+        chat_id: u64,
+        msg_id: u64,
+        user_id: u64,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let origin: Option<Message> = sqlx::query_as(
+            "SELECT * FROM messages WHERE id = $1 AND chat_id = $2 AND deleted_at IS NULL FOR UPDATE",
+        )
+        .bind(msg_id as i64)
         .bind(chat_id as i64)
-        .bind(last_id as i64)
-        .bind(limit)
-        .fetch_all(&self.pool).await?;
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let origin = match origin {
+            Some(msg) => msg,
+            None => return Err(AppError::NotFound(format!("message {}", msg_id))),
+        };
+
+        if origin.kind == MessageKind::System {
+            return Err(AppError::MessageError(
+                "system messages can't be deleted".to_string(),
+            ));
+        }
+        if origin.sender_id != Some(user_id as i64)
+            && self.workspace_owner_of_message(chat_id, msg_id).await? != user_id as i64
+        {
+            return Err(AppError::PermissionDenied(
+                "only the sender or the workspace owner can delete a message".to_string(),
+            ));
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE messages SET content = '', files = '{}', pinned_at = NULL, pinned_by = NULL, deleted_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            "#,
+        )
+        .bind(origin.id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.remove_unused_files(origin.files).await
+    }
+
+    // messages keep the urls the upload returned, only files of the sender's workspace
+    async fn verify_files(&self, user_id: u64, files: &[String]) -> Result<(), AppError> {
+        let (ws_id,): (i64,) = sqlx::query_as("SELECT ws_id FROM users WHERE id = $1")
+            .bind(user_id as i64)
+            .fetch_one(&self.pool)
+            .await?;
+
+        let base_dir = PathBuf::from(&self.config.server.base_dir).join(ws_id.to_string());
+        for url in files {
+            let exists = match ChatFile::from_url(url) {
+                Some((id, file)) if id == ws_id => file.path(&base_dir).exists(),
+                _ => false,
+            };
+            if !exists {
+                return Err(AppError::MessageError(format!(
+                    "file {} is not exists",
+                    url
+                )));
+            }
+        }
+        Ok(())
+    }
 
+    /// Files are stored by their hash, keep the ones other messages or a chat avatar still
+    /// refer to.
+    pub(crate) async fn remove_unused_files(&self, files: Vec<String>) -> Result<(), AppError> {
+        let base_dir = PathBuf::from(&self.config.server.base_dir);
+        for url in files {
+            let Some((ws_id, file)) = ChatFile::from_url(&url) else {
+                warn!("skip removing {}, it is not an uploaded file", url);
+                continue;
+            };
+
+            if self.is_file_referenced(ws_id, &file).await? {
+                continue;
+            }
+
+            let path = file.path(&base_dir.join(ws_id.to_string()));
+            if let Err(e) = remove_file_in(&base_dir, &path).await {
+                warn!("remove file {} failed: {}", path.display(), e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn is_file_referenced(&self, ws_id: i64, file: &ChatFile) -> Result<bool, AppError> {
+        let (referenced,): (bool,) = sqlx::query_as(
+            r#"
+            SELECT EXISTS(SELECT 1 FROM messages WHERE $1 = ANY(files))
+                OR EXISTS(SELECT 1 FROM chats WHERE avatar = $1)
+            "#,
+        )
+        .bind(file.url(ws_id.to_string()))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(referenced)
+    }
+
+    /// Pin a message to the top of the chat, or unpin it.
+    pub async fn pin_message(
+        &self,
+        chat_id: u64,
+        msg_id: u64,
+        user_id: u64,
+        pinned: bool,
+    ) -> Result<Message, AppError> {
+        let msg: Option<Message> = sqlx::query_as(
+            r#"
+            UPDATE messages SET
+                pinned_at = CASE WHEN $1 THEN COALESCE(pinned_at, CURRENT_TIMESTAMP) END,
+                pinned_by = CASE WHEN $1 THEN COALESCE(pinned_by, $2) END
+            WHERE id = $3 AND chat_id = $4 AND deleted_at IS NULL
+            RETURNING *
+            "#,
+        )
+        .bind(pinned)
+        .bind(user_id as i64)
+        .bind(msg_id as i64)
+        .bind(chat_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match msg {
+            Some(mut msg) => {
+                self.attach_reactions(std::slice::from_mut(&mut msg), user_id)
+                    .await?;
+                Ok(msg)
+            }
+            None => Err(AppError::NotFound(format!("message {}", msg_id))),
+        }
+    }
+
+    /// Pinned messages of the chat, the latest pin first.
+    pub async fn list_pinned_messages(
+        &self,
+        chat_id: u64,
+        user_id: u64,
+    ) -> Result<Vec<Message>, AppError> {
+        let mut messages: Vec<Message> = sqlx::query_as(&format!(
+            "SELECT {} FROM messages WHERE chat_id = $1 AND pinned_at IS NOT NULL ORDER BY pinned_at DESC",
+            MESSAGE_COLUMNS
+        ))
+        .bind(chat_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.attach_reactions(&mut messages, user_id).await?;
         Ok(messages)
     }
+
+    async fn workspace_owner_of_message(&self, chat_id: u64, msg_id: u64) -> Result<i64, AppError> {
+        let owner_id: Option<(i64,)> = sqlx::query_as(
+            r#"
+            SELECT workspaces.owner_id FROM messages
+            JOIN chats ON chats.id = messages.chat_id
+            JOIN workspaces ON workspaces.id = chats.ws_id
+            WHERE messages.id = $1 AND messages.chat_id = $2
+            "#,
+        )
+        .bind(msg_id as i64)
+        .bind(chat_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match owner_id {
+            Some((owner_id,)) => Ok(owner_id),
+            None => Err(AppError::NotFound(format!("message {}", msg_id))),
+        }
+    }
+}
+
+// never follow a path out of the upload directory, e.g. through a symlink
+async fn remove_file_in(base_dir: &Path, path: &Path) -> std::io::Result<()> {
+    let base_dir = tokio::fs::canonicalize(base_dir).await?;
+    let path = tokio::fs::canonicalize(path).await?;
+    if !path.starts_with(&base_dir) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "outside of the upload directory",
+        ));
+    }
+    tokio::fs::remove_file(path).await
+}
+
+/// Record a change to the chat in its timeline, the content is an english fallback
+/// for clients that don't render the event themselves.
+pub(crate) async fn create_system_message(
+    conn: &mut PgConnection,
+    chat_id: i64,
+    event: SystemEvent,
+) -> Result<(), AppError> {
+    let users: HashMap<i64, String> =
+        sqlx::query_as("SELECT id, fullname FROM users WHERE id = ANY($1)")
+            .bind(event.user_ids())
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .collect();
+
+    sqlx::query(
+        "INSERT INTO messages (chat_id, content, kind, payload) VALUES ($1, $2, 'system', $3)",
+    )
+    .bind(chat_id)
+    .bind(describe_event(&event, &users))
+    .bind(Json(event))
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+fn describe_event(event: &SystemEvent, users: &HashMap<i64, String>) -> String {
+    let name = |id: &i64| {
+        users
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| format!("user {}", id))
+    };
+    let names = |ids: &[i64]| ids.iter().map(name).collect::<Vec<_>>().join(", ");
+
+    match event {
+        SystemEvent::ChatCreated { actor_id } => format!("{} created the chat", name(actor_id)),
+        SystemEvent::ChatRenamed { actor_id, name: to } => {
+            format!("{} renamed the chat to \"{}\"", name(actor_id), to)
+        }
+        SystemEvent::TopicChanged {
+            actor_id,
+            topic: Some(topic),
+        } => format!("{} changed the topic to \"{}\"", name(actor_id), topic),
+        SystemEvent::TopicChanged {
+            actor_id,
+            topic: None,
+        } => format!("{} cleared the topic", name(actor_id)),
+        SystemEvent::ChatArchived { actor_id } => format!("{} archived the chat", name(actor_id)),
+        SystemEvent::ChatUnarchived { actor_id } => {
+            format!("{} unarchived the chat", name(actor_id))
+        }
+        SystemEvent::MembersAdded { actor_id, user_ids } => {
+            format!("{} added {}", name(actor_id), names(user_ids))
+        }
+        SystemEvent::MembersRemoved { actor_id, user_ids } => {
+            format!("{} removed {}", name(actor_id), names(user_ids))
+        }
+        SystemEvent::MemberJoined { user_id } => format!("{} joined the chat", name(user_id)),
+        SystemEvent::MemberLeft { user_id } => format!("{} left the chat", name(user_id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_edit_and_delete_message() -> anyhow::Result<()> {
+        let (app_state, users, chat) = AppState::new_for_test_with_chat(2).await?;
+        // the first user of the workspace owns it
+        let (owner, sender) = (&users[0], &users[1]);
+
+        let msg = app_state
+            .create_message(
+                chat.id as u64,
+                sender.id as u64,
+                &CreateMessage {
+                    content: "helo".to_string(),
+                    files: vec![],
+                    parent_id: None,
+                },
+            )
+            .await?;
+        assert!(!msg.edited);
+
+        let input = EditMessage {
+            content: "hello".to_string(),
+        };
+        let ret = app_state
+            .edit_message(chat.id as u64, msg.id as u64, owner.id as u64, &input)
+            .await;
+        assert!(matches!(ret, Err(AppError::PermissionDenied(_))));
+
+        let edited = app_state
+            .edit_message(chat.id as u64, msg.id as u64, sender.id as u64, &input)
+            .await?;
+        assert!(edited.edited);
+        assert_eq!(edited.content, "hello");
+
+        let ret = app_state
+            .list_message_edits(chat.id as u64, msg.id as u64, sender.id as u64)
+            .await;
+        assert!(matches!(ret, Err(AppError::PermissionDenied(_))));
+
+        let edits = app_state
+            .list_message_edits(chat.id as u64, msg.id as u64, owner.id as u64)
+            .await?;
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].content, "helo");
+
+        // the workspace owner can delete others' messages
+        app_state
+            .delete_message(chat.id as u64, msg.id as u64, owner.id as u64)
+            .await?;
+        let ret = app_state
+            .edit_message(chat.id as u64, msg.id as u64, sender.id as u64, &input)
+            .await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+
+        let msgs = app_state
+            .list_messages(chat.id as u64, owner.id as u64, ListMessages::default())
+            .await?
+            .messages;
+        // the tombstone and the creation of the chat
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs[0].deleted_at.is_some());
+        assert_eq!(msgs[0].content, "");
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_message_files_should_stay_in_workspace() -> anyhow::Result<()> {
+        let (app_state, users, chat) = AppState::new_for_test_with_chat(1).await?;
+        let user = &users[0];
+
+        // stored the way the upload does
+        let file = ChatFile::new("hello.txt", b"hello files");
+        let base_dir = PathBuf::from(&app_state.config.server.base_dir);
+        let path = file.path(&base_dir.join(user.ws_id.to_string()));
+        tokio::fs::create_dir_all(path.parent().expect("file has a parent")).await?;
+        tokio::fs::write(&path, b"hello files").await?;
+
+        let msg = |files: Vec<String>| CreateMessage {
+            content: "see attached".to_string(),
+            files,
+            parent_id: None,
+        };
+        let chat_id = chat.id as u64;
+        let user_id = user.id as u64;
+
+        let other_ws = file.url((user.ws_id + 1).to_string());
+        for url in ["../../etc/passwd".to_string(), other_ws] {
+            let ret = app_state
+                .create_message(chat_id, user_id, &msg(vec![url]))
+                .await;
+            assert!(matches!(ret, Err(AppError::MessageError(_))));
+        }
+
+        let url = file.url(user.ws_id.to_string());
+        let first = app_state
+            .create_message(chat_id, user_id, &msg(vec![url.clone()]))
+            .await?;
+        assert_eq!(first.files, vec![url.clone()]);
+        let second = app_state
+            .create_message(chat_id, user_id, &msg(vec![url.clone()]))
+            .await?;
+
+        // the file goes with the last message or chat avatar that refers to it
+        app_state
+            .delete_message(chat_id, first.id as u64, user_id)
+            .await?;
+        assert!(path.exists());
+        let set_avatar = "UPDATE chats SET avatar = $1 WHERE id = $2";
+        sqlx::query(set_avatar)
+            .bind(&url)
+            .bind(chat.id)
+            .execute(&app_state.pool)
+            .await?;
+        app_state
+            .delete_message(chat_id, second.id as u64, user_id)
+            .await?;
+        assert!(path.exists());
+        sqlx::query(set_avatar)
+            .bind(None::<String>)
+            .bind(chat.id)
+            .execute(&app_state.pool)
+            .await?;
+        app_state.remove_unused_files(vec![url]).await?;
+        assert!(!path.exists());
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_thread_replies() -> anyhow::Result<()> {
+        let (app_state, users, chat) = AppState::new_for_test_with_chat(1).await?;
+        let user = &users[0];
+
+        let new_msg = |content: &str, parent_id| CreateMessage {
+            content: content.to_string(),
+            files: vec![],
+            parent_id,
+        };
+        let (chat_id, user_id) = (chat.id as u64, user.id as u64);
+
+        let root = app_state
+            .create_message(chat_id, user_id, &new_msg("root", None))
+            .await?;
+        let reply = app_state
+            .create_message(chat_id, user_id, &new_msg("reply 1", Some(root.id)))
+            .await?;
+        app_state
+            .create_message(chat_id, user_id, &new_msg("reply 2", Some(root.id)))
+            .await?;
+
+        let ret = app_state
+            .create_message(chat_id, user_id, &new_msg("nested", Some(reply.id)))
+            .await;
+        assert!(matches!(ret, Err(AppError::MessageError(_))));
+
+        let input = ListMessages::default();
+        let timeline = app_state
+            .list_messages(chat_id, user_id, input.clone())
+            .await?
+            .messages;
+        // replies are left out, the creation of the chat isn't
+        assert_eq!(timeline.len(), 2);
+        assert_eq!(timeline[0].id, root.id);
+        assert_eq!(timeline[0].reply_count, 2);
+        assert!(timeline[0].last_reply_at.is_some());
+
+        let replies = app_state
+            .list_replies(chat_id, root.id as u64, user_id, input)
+            .await?
+            .messages;
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[1].content, "reply 1");
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_messages_should_page_both_ways() -> anyhow::Result<()> {
+        let (app_state, users, chat) = AppState::new_for_test_with_chat(1).await?;
+        let user = &users[0];
+        let (chat_id, user_id) = (chat.id as u64, user.id as u64);
+        // the chat starts with the system message of its creation
+        let created = app_state
+            .list_messages(chat_id, user_id, ListMessages::default())
+            .await?
+            .messages[0]
+            .id;
+
+        let mut ids = vec![];
+        for i in 0..10 {
+            let msg = app_state
+                .create_message(
+                    chat_id,
+                    user_id,
+                    &CreateMessage {
+                        content: format!("message {}", i),
+                        files: vec![],
+                        parent_id: None,
+                    },
+                )
+                .await?;
+            ids.push(msg.id);
+        }
+        let page_ids = |page: &MessagePage| page.messages.iter().map(|m| m.id).collect::<Vec<_>>();
+
+        // the latest page
+        let input = ListMessages {
+            limit: Some(4),
+            ..Default::default()
+        };
+        let page = app_state.list_messages(chat_id, user_id, input).await?;
+        assert_eq!(page_ids(&page), vec![ids[9], ids[8], ids[7], ids[6]]);
+        assert!(page.has_more);
+        assert_eq!(page.next_cursor, Some(ids[6]));
+        assert_eq!(page.prev_cursor, None);
+
+        let input = ListMessages {
+            before: Some(ids[2]),
+            limit: Some(4),
+            ..Default::default()
+        };
+        let page = app_state.list_messages(chat_id, user_id, input).await?;
+        assert_eq!(page_ids(&page), vec![ids[1], ids[0], created]);
+        assert!(!page.has_more);
+        assert_eq!(page.prev_cursor, Some(ids[1]));
+
+        let input = ListMessages {
+            after: Some(ids[6]),
+            limit: Some(4),
+            ..Default::default()
+        };
+        let page = app_state.list_messages(chat_id, user_id, input).await?;
+        assert_eq!(page_ids(&page), vec![ids[9], ids[8], ids[7]]);
+        assert!(!page.has_more);
+
+        let input = ListMessages {
+            around: Some(ids[5]),
+            limit: Some(4),
+            ..Default::default()
+        };
+        let page = app_state.list_messages(chat_id, user_id, input).await?;
+        assert_eq!(page_ids(&page), vec![ids[7], ids[6], ids[5], ids[4]]);
+        assert!(page.has_more);
+        assert_eq!(page.next_cursor, Some(ids[4]));
+        assert_eq!(page.prev_cursor, Some(ids[7]));
+
+        let input = ListMessages {
+            before: Some(ids[5]),
+            after: Some(ids[1]),
+            ..Default::default()
+        };
+        let ret = app_state.list_messages(chat_id, user_id, input).await;
+        assert!(matches!(ret, Err(AppError::MessageError(_))));
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
 }
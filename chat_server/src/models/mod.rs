@@ -1,26 +1,20 @@
+pub mod channel;
 pub mod chat;
 pub mod file;
+pub mod invite;
+pub mod mention;
 pub mod message;
+pub mod reaction;
+pub mod read;
+pub mod role;
+pub mod search;
 pub mod user;
 pub mod workspace;
 
-use chrono::{DateTime, Utc};
+pub use chat_core::{Chat, ChatType, Message, User};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
-#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
-pub struct User {
-    pub id: i64,
-    pub ws_id: i64,
-    pub fullname: String,
-    pub email: String,
-
-    #[sqlx(default)]
-    #[serde(skip)]
-    pub password_hash: Option<String>,
-    pub created_at: chrono::DateTime<chrono::Utc>,
-}
-
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
 pub struct Workspace {
     pub id: i64,
@@ -28,38 +22,3 @@ pub struct Workspace {
     pub owner_id: i64,
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
-
-#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
-pub struct Message {
-    pub id: i64,
-    pub chat_id: i64,
-    pub sender_id: i64,
-    pub content: String,
-    pub files: Vec<String>,
-    pub created_at: DateTime<Utc>,
-    pub updated_at: DateTime<Utc>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, sqlx::Type)]
-#[sqlx(type_name = "chat_type", rename_all = "snake_case")]
-#[serde(rename_all(serialize = "camelCase"))]
-pub enum ChatType {
-    #[serde(alias = "single", alias = "Single")]
-    Single,
-    #[serde(alias = "group", alias = "Group")]
-    Group,
-    #[serde(alias = "private_channel", alias = "privateChannel")]
-    PrivateChannel,
-    #[serde(alias = "public_channel", alias = "PublicChannel")]
-    PublicChannel,
-}
-
-#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
-pub struct Chat {
-    pub id: i64,
-    pub ws_id: i64,
-    pub name: String,
-    pub r#type: ChatType,
-    pub members: Vec<i64>,
-    pub created_at: DateTime<Utc>,
-}
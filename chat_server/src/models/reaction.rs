@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use chat_core::ReactionCount;
+use serde::{Deserialize, Serialize};
+
+use super::Message;
+use crate::{error::AppError, AppState};
+
+const MAX_EMOJI_LEN: usize = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CreateReaction {
+    pub emoji: String,
+}
+
+impl AppState {
+    /// Reacting twice with the same emoji is a no-op.
+    pub async fn add_reaction(
+        &self,
+        chat_id: u64,
+        msg_id: u64,
+        user_id: u64,
+        emoji: &str,
+    ) -> Result<Vec<ReactionCount>, AppError> {
+        if emoji.is_empty() || emoji.chars().count() > MAX_EMOJI_LEN {
+            return Err(AppError::MessageError("invalid emoji".to_string()));
+        }
+        self.check_reaction(chat_id, msg_id, user_id).await?;
+
+        sqlx::query(
+            "INSERT INTO message_reactions (message_id, user_id, emoji) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        )
+        .bind(msg_id as i64)
+        .bind(user_id as i64)
+        .bind(emoji)
+        .execute(&self.pool)
+        .await?;
+
+        let mut reactions = self.list_reactions(&[msg_id as i64], user_id).await?;
+        Ok(reactions.remove(&(msg_id as i64)).unwrap_or_default())
+    }
+
+    pub async fn remove_reaction(
+        &self,
+        chat_id: u64,
+        msg_id: u64,
+        user_id: u64,
+        emoji: &str,
+    ) -> Result<(), AppError> {
+        self.check_reaction(chat_id, msg_id, user_id).await?;
+
+        let ret = sqlx::query(
+            "DELETE FROM message_reactions WHERE message_id = $1 AND user_id = $2 AND emoji = $3",
+        )
+        .bind(msg_id as i64)
+        .bind(user_id as i64)
+        .bind(emoji)
+        .execute(&self.pool)
+        .await?;
+
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("reaction {}", emoji)));
+        }
+        Ok(())
+    }
+
+    /// Reaction counts of the messages, in the order the emojis were first used.
+    pub async fn list_reactions(
+        &self,
+        msg_ids: &[i64],
+        user_id: u64,
+    ) -> Result<HashMap<i64, Vec<ReactionCount>>, AppError> {
+        let rows: Vec<(i64, String, i64, bool)> = sqlx::query_as(
+            r#"
+            SELECT message_id, emoji, count(*), bool_or(user_id = $2)
+            FROM message_reactions
+            WHERE message_id = ANY($1)
+            GROUP BY message_id, emoji
+            ORDER BY message_id, min(created_at)
+            "#,
+        )
+        .bind(msg_ids)
+        .bind(user_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut reactions: HashMap<i64, Vec<ReactionCount>> = HashMap::new();
+        for (msg_id, emoji, count, me) in rows {
+            reactions
+                .entry(msg_id)
+                .or_default()
+                .push(ReactionCount { emoji, count, me });
+        }
+        Ok(reactions)
+    }
+
+    pub(crate) async fn attach_reactions(
+        &self,
+        messages: &mut [Message],
+        user_id: u64,
+    ) -> Result<(), AppError> {
+        let ids: Vec<i64> = messages.iter().map(|msg| msg.id).collect();
+        let mut reactions = self.list_reactions(&ids, user_id).await?;
+        for msg in messages {
+            msg.reactions = reactions.remove(&msg.id).unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    // only chat members react, and not on deleted messages
+    async fn check_reaction(
+        &self,
+        chat_id: u64,
+        msg_id: u64,
+        user_id: u64,
+    ) -> Result<(), AppError> {
+        if !self.is_chat_member(chat_id, user_id).await? {
+            return Err(AppError::PermissionDenied(format!(
+                "user {} is not a member of chat {}",
+                user_id, chat_id
+            )));
+        }
+
+        let msg: Option<(i64,)> = sqlx::query_as(
+            "SELECT id FROM messages WHERE id = $1 AND chat_id = $2 AND deleted_at IS NULL",
+        )
+        .bind(msg_id as i64)
+        .bind(chat_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match msg {
+            Some(_) => Ok(()),
+            None => Err(AppError::NotFound(format!("message {}", msg_id))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::message::CreateMessage;
+
+    #[tokio::test]
+    async fn test_reactions_should_be_counted() -> anyhow::Result<()> {
+        let app_state = AppState::new_for_test().await?;
+
+        let users = app_state.create_test_users(3).await?;
+        let chat = app_state
+            .create_test_chat("reactions", &[&users[0], &users[1]])
+            .await?;
+        let users: Vec<u64> = users.iter().map(|user| user.id as u64).collect();
+        let chat_id = chat.id as u64;
+
+        let msg = app_state
+            .create_message(
+                chat_id,
+                users[0],
+                &CreateMessage {
+                    content: "hello".to_string(),
+                    files: vec![],
+                    parent_id: None,
+                },
+            )
+            .await?;
+        let msg_id = msg.id as u64;
+
+        app_state
+            .add_reaction(chat_id, msg_id, users[0], "👍")
+            .await?;
+        app_state
+            .add_reaction(chat_id, msg_id, users[1], "🎉")
+            .await?;
+        let reactions = app_state
+            .add_reaction(chat_id, msg_id, users[1], "👍")
+            .await?;
+        assert_eq!(
+            reactions,
+            vec![
+                ReactionCount {
+                    emoji: "👍".to_string(),
+                    count: 2,
+                    me: true
+                },
+                ReactionCount {
+                    emoji: "🎉".to_string(),
+                    count: 1,
+                    me: true
+                },
+            ]
+        );
+
+        // not a member of the chat
+        let ret = app_state
+            .add_reaction(chat_id, msg_id, users[2], "👍")
+            .await;
+        assert!(matches!(ret, Err(AppError::PermissionDenied(_))));
+
+        app_state
+            .remove_reaction(chat_id, msg_id, users[1], "👍")
+            .await?;
+        let reactions = app_state.list_reactions(&[msg.id], users[0]).await?;
+        assert_eq!(reactions[&msg.id][0].count, 1);
+        assert!(reactions[&msg.id][0].me);
+        assert!(!reactions[&msg.id][1].me);
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use chat_core::{ChatRead, ChatType};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::{error::AppError, AppState};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MarkRead {
+    pub message_id: i64,
+}
+
+// a member who has read a message
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct ReadReceipt {
+    pub user_id: i64,
+    pub read_at: DateTime<Utc>,
+}
+
+impl AppState {
+    /// Move the read position of the user forward, it never goes back.
+    pub async fn mark_chat_read(
+        &self,
+        chat_id: u64,
+        user_id: u64,
+        message_id: i64,
+    ) -> Result<ChatRead, AppError> {
+        if !self.is_chat_member(chat_id, user_id).await? {
+            return Err(AppError::PermissionDenied(format!(
+                "user {} is not a member of chat {}",
+                user_id, chat_id
+            )));
+        }
+
+        ChatRead::mark(&self.pool, chat_id as i64, user_id as i64, message_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("message {}", message_id)))
+    }
+
+    /// Members who have read the message, channels are too big for read receipts.
+    pub async fn list_read_receipts(
+        &self,
+        chat_id: u64,
+        msg_id: u64,
+        user_id: u64,
+    ) -> Result<Vec<ReadReceipt>, AppError> {
+        let chat = match self.get_chat_by_id(chat_id).await? {
+            Some(chat) => chat,
+            None => return Err(AppError::NotFound(format!("chat {}", chat_id))),
+        };
+        if !chat.members.contains(&(user_id as i64)) {
+            return Err(AppError::PermissionDenied(format!(
+                "user {} is not a member of chat {}",
+                user_id, chat_id
+            )));
+        }
+        if !matches!(chat.r#type, ChatType::Single | ChatType::Group) {
+            return Err(AppError::ChatError(
+                "read receipts are only available in single and group chats".to_string(),
+            ));
+        }
+
+        let sender: Option<(Option<i64>,)> =
+            sqlx::query_as("SELECT sender_id FROM messages WHERE id = $1 AND chat_id = $2")
+                .bind(msg_id as i64)
+                .bind(chat_id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+        let (sender_id,) = match sender {
+            Some(sender) => sender,
+            None => return Err(AppError::NotFound(format!("message {}", msg_id))),
+        };
+
+        let receipts = sqlx::query_as(
+            r#"
+            SELECT user_id, updated_at AS read_at FROM chat_reads
+            WHERE chat_id = $1 AND last_read_message_id >= $2 AND user_id IS DISTINCT FROM $3
+            ORDER BY updated_at
+            "#,
+        )
+        .bind(chat_id as i64)
+        .bind(msg_id as i64)
+        .bind(sender_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(receipts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{chat::ListChats, message::CreateMessage};
+
+    #[tokio::test]
+    async fn test_read_position_and_receipts() -> anyhow::Result<()> {
+        let app_state = AppState::new_for_test().await?;
+        let pool = app_state.pool.clone();
+
+        let users = app_state.create_test_users(3).await?;
+        let chat = app_state
+            .create_test_chat("reads", &[&users[0], &users[1]])
+            .await?;
+        let (alice, bob, carol) = (users[0].id as u64, users[1].id as u64, users[2].id as u64);
+        let ws_id = users[0].ws_id as u64;
+        let chat_id = chat.id as u64;
+
+        let mut ids = vec![];
+        for content in ["hi", "hi @bob", "are you there?"] {
+            let msg = app_state
+                .create_message(
+                    chat_id,
+                    alice,
+                    &CreateMessage {
+                        content: content.to_string(),
+                        files: vec![],
+                        parent_id: None,
+                    },
+                )
+                .await?;
+            ids.push(msg.id);
+        }
+
+        let chats = app_state
+            .list_chats(bob, ws_id, ListChats::default())
+            .await?
+            .chats;
+        assert_eq!(chats[0].unread_count, 3);
+        assert_eq!(chats[0].unread_mentions, 1);
+
+        // alice sent them all
+        let chats = app_state
+            .list_chats(alice, ws_id, ListChats::default())
+            .await?
+            .chats;
+        assert_eq!(chats[0].unread_count, 0);
+
+        // the history before carol joined isn't unread for her
+        sqlx::query("INSERT INTO chat_members (chat_id, user_id) VALUES ($1, $2)")
+            .bind(chat_id as i64)
+            .bind(carol as i64)
+            .execute(&pool)
+            .await?;
+        let chats = app_state
+            .list_chats(carol, ws_id, ListChats::default())
+            .await?
+            .chats;
+        assert_eq!(chats[0].unread_count, 0);
+
+        let read = app_state.mark_chat_read(chat_id, bob, ids[1]).await?;
+        assert_eq!(read.last_read_message_id, ids[1]);
+
+        // marking an older message read doesn't move the position back
+        let read = app_state.mark_chat_read(chat_id, bob, ids[0]).await?;
+        assert_eq!(read.last_read_message_id, ids[1]);
+
+        let chats = app_state
+            .list_chats(bob, ws_id, ListChats::default())
+            .await?
+            .chats;
+        assert_eq!(chats[0].last_read_message_id, ids[1]);
+        assert_eq!(chats[0].unread_count, 1);
+        assert_eq!(chats[0].unread_mentions, 0);
+
+        let ret = app_state
+            .list_read_receipts(chat_id, ids[0] as u64, alice)
+            .await;
+        assert!(matches!(ret, Err(AppError::ChatError(_))));
+
+        let (group_id,): (i64,) = sqlx::query_as(
+            "INSERT INTO chats (ws_id, name, type) VALUES ($1, '', 'group') RETURNING id",
+        )
+        .bind(ws_id as i64)
+        .fetch_one(&pool)
+        .await?;
+        sqlx::query("INSERT INTO chat_members (chat_id, user_id) VALUES ($1, $2), ($1, $3)")
+            .bind(group_id)
+            .bind(alice as i64)
+            .bind(bob as i64)
+            .execute(&pool)
+            .await?;
+        let group_id = group_id as u64;
+        let msg = app_state
+            .create_message(
+                group_id,
+                alice,
+                &CreateMessage {
+                    content: "lunch?".to_string(),
+                    files: vec![],
+                    parent_id: None,
+                },
+            )
+            .await?;
+
+        let receipts = app_state
+            .list_read_receipts(group_id, msg.id as u64, alice)
+            .await?;
+        assert!(receipts.is_empty());
+
+        app_state.mark_chat_read(group_id, bob, msg.id).await?;
+        let receipts = app_state
+            .list_read_receipts(group_id, msg.id as u64, alice)
+            .await?;
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].user_id, bob as i64);
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
+}
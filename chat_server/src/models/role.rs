@@ -0,0 +1,361 @@
+use chat_core::ChatRole;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection};
+
+use super::{
+    chat::{verify_members_keep, UpdateChat},
+    Chat,
+};
+use crate::{error::AppError, AppState};
+
+/// Changes to a chat that depend on the role of the member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatAction {
+    // the name, topic, description and avatar
+    Rename,
+    ChangeMembers,
+    ChangeType,
+    Archive,
+    Pin,
+    ManageRoles,
+    Invite,
+}
+
+impl ChatAction {
+    pub fn allowed_for(self, role: ChatRole) -> bool {
+        match self {
+            ChatAction::Rename | ChatAction::ChangeMembers | ChatAction::Pin => {
+                role <= ChatRole::Admin
+            }
+            ChatAction::ChangeType
+            | ChatAction::Archive
+            | ChatAction::ManageRoles
+            | ChatAction::Invite => role == ChatRole::Owner,
+        }
+    }
+
+    fn check(self, chat_id: i64, role: ChatRole) -> Result<(), AppError> {
+        if !self.allowed_for(role) {
+            return Err(AppError::PermissionDenied(format!(
+                "{:?} of chat {} is not allowed for {:?}",
+                self, chat_id, role
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct ChatMember {
+    pub user_id: i64,
+    pub role: ChatRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateChatRole {
+    pub role: ChatRole,
+}
+
+impl AppState {
+    pub async fn get_chat_role(&self, chat_id: u64, user_id: u64) -> Result<ChatRole, AppError> {
+        let role: Option<(ChatRole,)> =
+            sqlx::query_as("SELECT role FROM chat_members WHERE chat_id = $1 AND user_id = $2")
+                .bind(chat_id as i64)
+                .bind(user_id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        match role {
+            Some((role,)) => Ok(role),
+            None => Err(AppError::PermissionDenied(format!(
+                "user {} is not a member of chat {}",
+                user_id, chat_id
+            ))),
+        }
+    }
+
+    pub async fn check_chat_permission(
+        &self,
+        chat_id: u64,
+        user_id: u64,
+        action: ChatAction,
+    ) -> Result<ChatRole, AppError> {
+        let role = self.get_chat_role(chat_id, user_id).await?;
+        action.check(chat_id as i64, role)?;
+        Ok(role)
+    }
+
+    /// Every change needs the matching permission, members leave with `leave_chat`.
+    pub async fn check_chat_update(
+        &self,
+        chat: &Chat,
+        user_id: u64,
+        input: &UpdateChat,
+    ) -> Result<(), AppError> {
+        let role = self.get_chat_role(chat.id as u64, user_id).await?;
+
+        // an empty value clears the info
+        let changed = |new: &Option<String>, old: &Option<String>| {
+            new.as_deref()
+                .is_some_and(|new| Some(new).filter(|new| !new.is_empty()) != old.as_deref())
+        };
+        if input.name.as_ref().is_some_and(|name| *name != chat.name)
+            || changed(&input.topic, &chat.topic)
+            || changed(&input.description, &chat.description)
+            || changed(&input.avatar, &chat.avatar)
+        {
+            ChatAction::Rename.check(chat.id, role)?;
+        }
+
+        if input.chat_type.as_ref().is_some_and(|t| *t != chat.r#type) {
+            ChatAction::ChangeType.check(chat.id, role)?;
+        }
+
+        let Some(members) = &input.members else {
+            return Ok(());
+        };
+        verify_members_keep(members, user_id)?;
+        let added = members.iter().any(|id| !chat.members.contains(id));
+        let removed: Vec<i64> = chat
+            .members
+            .iter()
+            .filter(|id| !members.contains(id))
+            .copied()
+            .collect();
+        if !added && removed.is_empty() {
+            return Ok(());
+        }
+
+        ChatAction::ChangeMembers.check(chat.id, role)?;
+        // members can only be removed by someone who outranks them
+        for member in self.list_chat_members(chat.id as u64).await? {
+            if removed.contains(&member.user_id) && member.role <= role {
+                return Err(AppError::PermissionDenied(format!(
+                    "{:?} can't remove {:?} {} from chat {}",
+                    role, member.role, member.user_id, chat.id
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Members of the chat with their roles, the owner first.
+    pub async fn list_chat_members(&self, chat_id: u64) -> Result<Vec<ChatMember>, AppError> {
+        let members = sqlx::query_as(
+            r#"
+            SELECT user_id, role, joined_at FROM chat_members
+            WHERE chat_id = $1
+            ORDER BY role, joined_at, user_id
+            "#,
+        )
+        .bind(chat_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(members)
+    }
+
+    /// Only the owner changes roles, making someone else owner hands the ownership over.
+    pub async fn set_chat_role(
+        &self,
+        chat: &Chat,
+        user_id: u64,
+        member_id: u64,
+        role: ChatRole,
+    ) -> Result<ChatMember, AppError> {
+        self.check_chat_permission(chat.id as u64, user_id, ChatAction::ManageRoles)
+            .await?;
+
+        if !chat.members.contains(&(member_id as i64)) {
+            return Err(AppError::NotFound(format!(
+                "member {} of chat {}",
+                member_id, chat.id
+            )));
+        }
+        if member_id == user_id {
+            return Err(AppError::ChatError(
+                "the owner can only hand the ownership over".to_string(),
+            ));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        if role == ChatRole::Owner {
+            save_chat_role(&mut tx, chat.id, user_id as i64, ChatRole::Admin).await?;
+        }
+        let member = save_chat_role(&mut tx, chat.id, member_id as i64, role).await?;
+        tx.commit().await?;
+
+        Ok(member)
+    }
+}
+
+pub(crate) async fn save_chat_role(
+    conn: &mut PgConnection,
+    chat_id: i64,
+    user_id: i64,
+    role: ChatRole,
+) -> Result<ChatMember, AppError> {
+    let member = sqlx::query_as(
+        r#"
+        UPDATE chat_members SET role = $3 WHERE chat_id = $1 AND user_id = $2
+        RETURNING user_id, role, joined_at
+        "#,
+    )
+    .bind(chat_id)
+    .bind(user_id)
+    .bind(role)
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(member)
+}
+
+/// The chat lost its owner, the longest standing admin takes over, or member if there is none.
+pub(crate) async fn transfer_ownership(
+    conn: &mut PgConnection,
+    chat_id: i64,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        UPDATE chat_members SET role = 'owner'
+        WHERE chat_id = $1 AND user_id = (
+            SELECT user_id FROM chat_members WHERE chat_id = $1
+            ORDER BY role, joined_at, user_id
+            LIMIT 1
+        )
+        "#,
+    )
+    .bind(chat_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_actions_should_follow_roles() {
+        use ChatAction::*;
+
+        for action in [
+            Rename,
+            ChangeMembers,
+            ChangeType,
+            Archive,
+            Pin,
+            ManageRoles,
+            Invite,
+        ] {
+            assert!(action.allowed_for(ChatRole::Owner));
+            assert!(!action.allowed_for(ChatRole::Member));
+        }
+
+        for action in [Rename, ChangeMembers, Pin] {
+            assert!(action.allowed_for(ChatRole::Admin));
+        }
+        for action in [ChangeType, Archive, ManageRoles, Invite] {
+            assert!(!action.allowed_for(ChatRole::Admin));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_roles_should_be_enforced() -> anyhow::Result<()> {
+        let (app_state, users, chat) = AppState::new_for_test_with_chat(4).await?;
+        let users: Vec<i64> = users.iter().map(|user| user.id).collect();
+        let (alice, bob, carol, dave) = (users[0], users[1], users[2], users[3]);
+
+        assert_eq!(
+            app_state
+                .get_chat_role(chat.id as u64, alice as u64)
+                .await?,
+            ChatRole::Owner
+        );
+
+        app_state
+            .set_chat_role(&chat, alice as u64, bob as u64, ChatRole::Admin)
+            .await?;
+        // only the owner manages roles
+        assert!(app_state
+            .set_chat_role(&chat, bob as u64, carol as u64, ChatRole::Admin)
+            .await
+            .is_err());
+
+        let rename = UpdateChat {
+            name: Some("renamed".to_string()),
+            chat_type: None,
+            members: None,
+            ..Default::default()
+        };
+        assert!(app_state
+            .check_chat_update(&chat, carol as u64, &rename)
+            .await
+            .is_err());
+        app_state
+            .check_chat_update(&chat, bob as u64, &rename)
+            .await?;
+
+        // admins remove members, but not the owner
+        let kick = |id: i64| UpdateChat {
+            name: None,
+            chat_type: None,
+            members: Some(users.iter().copied().filter(|m| *m != id).collect()),
+            ..Default::default()
+        };
+        app_state
+            .check_chat_update(&chat, bob as u64, &kick(dave))
+            .await?;
+        assert!(app_state
+            .check_chat_update(&chat, bob as u64, &kick(alice))
+            .await
+            .is_err());
+        assert!(app_state
+            .check_chat_update(&chat, carol as u64, &kick(dave))
+            .await
+            .is_err());
+
+        // an update can't drop its author nor empty the chat, members leave with leave_chat
+        let empty = UpdateChat {
+            members: Some(vec![]),
+            ..Default::default()
+        };
+        for input in [kick(alice), empty] {
+            assert!(matches!(
+                app_state
+                    .check_chat_update(&chat, alice as u64, &input)
+                    .await,
+                Err(AppError::ChatError(_))
+            ));
+            assert!(matches!(
+                app_state
+                    .update_chat_by_id(chat.id as u64, alice as u64, chat.clone(), input)
+                    .await,
+                Err(AppError::ChatError(_))
+            ));
+        }
+
+        // the admin takes over from a leaving owner
+        app_state.leave_chat(&chat, alice as u64).await?;
+
+        let members: Vec<(i64, ChatRole)> = app_state
+            .list_chat_members(chat.id as u64)
+            .await?
+            .into_iter()
+            .map(|member| (member.user_id, member.role))
+            .collect();
+        assert_eq!(
+            members,
+            vec![
+                (bob, ChatRole::Owner),
+                (carol, ChatRole::Member),
+                (dave, ChatRole::Member)
+            ]
+        );
+
+        app_state.cleanup_for_test().await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+
+use super::{Message, User};
+use crate::{error::AppError, AppState};
+
+const DEFAULT_SEARCH_LIMIT: i64 = 20;
+const MAX_SEARCH_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchMessages {
+    pub q: String,
+    // id of the last message of the previous page
+    pub cursor: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct SearchHit {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub message: Message,
+    // the matched content, with the terms wrapped in <mark></mark>
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SearchResult {
+    pub messages: Vec<SearchHit>,
+    pub next_cursor: Option<i64>,
+}
+
+/// `deploy from:alice in:ops after:2024-11-01 has:file`, the filters are combined with AND.
+#[derive(Debug, Default, PartialEq)]
+struct SearchQuery {
+    text: String,
+    from: Vec<String>,
+    chats: Vec<String>,
+    before: Option<NaiveDate>,
+    after: Option<NaiveDate>,
+    has_file: bool,
+}
+
+impl SearchQuery {
+    fn parse(q: &str) -> Result<Self, AppError> {
+        let mut query = SearchQuery::default();
+        let mut words = vec![];
+
+        for word in q.split_whitespace() {
+            match word.split_once(':') {
+                Some(("from", name)) => {
+                    query.from.push(name.trim_start_matches('@').to_lowercase())
+                }
+                Some(("in", name)) => query
+                    .chats
+                    .push(name.trim_start_matches('#').to_lowercase()),
+                Some(("before", date)) => query.before = Some(parse_date(date)?),
+                Some(("after", date)) => query.after = Some(parse_date(date)?),
+                Some(("has", "file")) => query.has_file = true,
+                _ => words.push(word),
+            }
+        }
+        query.text = words.join(" ");
+
+        if query == SearchQuery::default() {
+            return Err(AppError::MessageError("search query is empty".to_string()));
+        }
+        Ok(query)
+    }
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, AppError> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| AppError::MessageError(format!("invalid date {}, expect YYYY-MM-DD", date)))
+}
+
+impl AppState {
+    /// Search the messages of the chats the user is a member of, newest first.
+    pub async fn search_messages(
+        &self,
+        user: &User,
+        input: SearchMessages,
+    ) -> Result<SearchResult, AppError> {
+        let query = SearchQuery::parse(&input.q)?;
+        let limit = input
+            .limit
+            .unwrap_or(DEFAULT_SEARCH_LIMIT)
+            .clamp(1, MAX_SEARCH_LIMIT);
+
+        let mut qb: QueryBuilder<Postgres> = QueryBuilder::new("SELECT messages.*, ");
+        if query.text.is_empty() {
+            qb.push("messages.content AS snippet");
+        } else {
+            qb.push("ts_headline('english', messages.content, websearch_to_tsquery('english', ")
+                .push_bind(&query.text)
+                .push("), 'StartSel=<mark>, StopSel=</mark>') AS snippet");
+        }
+
+        qb.push(" FROM messages JOIN chats ON chats.id = messages.chat_id")
+            .push(
+                " JOIN chat_members ON chat_members.chat_id = chats.id AND chat_members.user_id = ",
+            )
+            .push_bind(user.id)
+            .push(" WHERE chats.ws_id = ")
+            .push_bind(user.ws_id)
+            .push(" AND messages.deleted_at IS NULL");
+
+        if !query.text.is_empty() {
+            qb.push(" AND messages.content_tsv @@ websearch_to_tsquery('english', ")
+                .push_bind(&query.text)
+                .push(")");
+        }
+        if !query.from.is_empty() {
+            // same names as @mentions: the local part of the email, or the fullname without spaces
+            qb.push(" AND messages.sender_id IN (SELECT id FROM users WHERE ws_id = ")
+                .push_bind(user.ws_id)
+                .push(" AND (lower(split_part(email, '@', 1)) = ANY(")
+                .push_bind(&query.from)
+                .push(") OR lower(replace(fullname, ' ', '')) = ANY(")
+                .push_bind(&query.from)
+                .push(")))");
+        }
+        if !query.chats.is_empty() {
+            qb.push(" AND lower(chats.name) = ANY(")
+                .push_bind(&query.chats)
+                .push(")");
+        }
+        if let Some(before) = query.before {
+            qb.push(" AND messages.created_at < ").push_bind(before);
+        }
+        if let Some(after) = query.after {
+            // both days are excluded, after:2024-11-01 starts on 2024-11-02
+            qb.push(" AND messages.created_at >= ")
+                .push_bind(after)
+                .push(" + interval '1 day'");
+        }
+        if query.has_file {
+            qb.push(" AND cardinality(messages.files) > 0");
+        }
+        if let Some(cursor) = input.cursor {
+            qb.push(" AND messages.id < ").push_bind(cursor);
+        }
+
+        // fetch one more to know whether there is a next page
+        qb.push(" ORDER BY messages.id DESC LIMIT ")
+            .push_bind(limit + 1);
+
+        let mut messages: Vec<SearchHit> = qb.build_query_as().fetch_all(&self.pool).await?;
+
+        let next_cursor = if messages.len() as i64 > limit {
+            messages.truncate(limit as usize);
+            messages.last().map(|hit| hit.message.id)
+        } else {
+            None
+        };
+
+        Ok(SearchResult {
+            messages,
+            next_cursor,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_query_should_parse_filters() -> anyhow::Result<()> {
+        let query =
+            SearchQuery::parse("deploy from:@Alice in:#ops after:2024-11-01 has:file failed")?;
+        assert_eq!(
+            query,
+            SearchQuery {
+                text: "deploy failed".to_string(),
+                from: vec!["alice".to_string()],
+                chats: vec!["ops".to_string()],
+                before: None,
+                after: NaiveDate::from_ymd_opt(2024, 11, 1),
+                has_file: true,
+            }
+        );
+
+        assert!(SearchQuery::parse("  ").is_err());
+        assert!(SearchQuery::parse("before:yesterday").is_err());
+        Ok(())
+    }
+}
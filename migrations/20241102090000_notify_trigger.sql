@@ -0,0 +1,36 @@
+-- notify chat changes (create / update / delete) to notify_server; only the ids are sent,
+-- pg_notify rejects payloads over 8000 bytes, notify_server loads the rows itself
+CREATE OR REPLACE FUNCTION chat_updated()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    RAISE NOTICE 'chat_updated: %, %', TG_OP, COALESCE(NEW.id, OLD.id);
+    PERFORM pg_notify('chat_updated', json_build_object('op', TG_OP, 'chat_id', COALESCE(NEW.id, OLD.id))::text);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER chat_updated_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON chats
+    FOR EACH ROW
+    EXECUTE FUNCTION chat_updated();
+
+-- notify message changes, notify_server loads the message and the members of its chat
+CREATE OR REPLACE FUNCTION message_updated()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    RAISE NOTICE 'message_updated: %, %', TG_OP, NEW.id;
+    PERFORM pg_notify('message_updated', json_build_object('op', TG_OP, 'chat_id', NEW.chat_id, 'message_id', NEW.id)::text);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+-- a deleted message can't be loaded by its id, messages are only deleted for good
+-- together with their chat
+CREATE OR REPLACE TRIGGER message_updated_trigger
+    AFTER INSERT OR UPDATE ON messages
+    FOR EACH ROW
+    EXECUTE FUNCTION message_updated();
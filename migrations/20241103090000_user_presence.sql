@@ -0,0 +1,8 @@
+-- presence of users, maintained by notify_server
+CREATE TYPE user_presence AS ENUM (
+    'online',
+    'away',
+    'offline'
+);
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS presence user_presence NOT NULL DEFAULT 'offline';
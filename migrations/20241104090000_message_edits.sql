@@ -0,0 +1,11 @@
+-- edits of messages, the previous content of a message is kept in message_edits
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS edited boolean NOT NULL DEFAULT false;
+
+CREATE TABLE IF NOT EXISTS message_edits (
+    id bigserial PRIMARY KEY,
+    message_id bigint NOT NULL REFERENCES messages(id),
+    content text NOT NULL,
+    edited_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_edits_message_id ON message_edits(message_id);
@@ -0,0 +1,2 @@
+-- a deleted message stays as a tombstone, with its content and files cleared
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS deleted_at timestamptz;
@@ -0,0 +1,6 @@
+-- threads: a reply points to its root message, the root keeps a summary of its replies
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS parent_id bigint REFERENCES messages(id);
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS reply_count integer NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS last_reply_at timestamptz;
+
+CREATE INDEX IF NOT EXISTS idx_messages_parent_id ON messages(parent_id, id DESC);
@@ -0,0 +1,41 @@
+-- emoji reactions on messages
+CREATE TABLE IF NOT EXISTS message_reactions (
+    message_id bigint NOT NULL REFERENCES messages(id),
+    user_id bigint NOT NULL REFERENCES users(id),
+    emoji varchar(32) NOT NULL,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (message_id, user_id, emoji)
+);
+
+-- notify reactions together with the chat and its members
+CREATE OR REPLACE FUNCTION reaction_updated()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    r message_reactions;
+    chat bigint;
+    users bigint[];
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        r := OLD;
+    ELSE
+        r := NEW;
+    END IF;
+
+    SELECT chats.id, chats.members INTO chat, users FROM messages
+        JOIN chats ON chats.id = messages.chat_id
+        WHERE messages.id = r.message_id;
+
+    RAISE NOTICE 'reaction_updated: %, %', TG_OP, r.message_id;
+    PERFORM pg_notify('reaction_updated', json_build_object('op', TG_OP,
+        'reaction', json_build_object('chat_id', chat, 'message_id', r.message_id, 'user_id', r.user_id, 'emoji', r.emoji),
+        'members', users)::text);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER reaction_updated_trigger
+    AFTER INSERT OR DELETE ON message_reactions
+    FOR EACH ROW
+    EXECUTE FUNCTION reaction_updated();
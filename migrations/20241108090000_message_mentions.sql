@@ -0,0 +1,38 @@
+-- mentions resolved from the content of messages
+CREATE TYPE mention_kind AS ENUM (
+    'channel',
+    'here',
+    'user'
+);
+
+CREATE TABLE IF NOT EXISTS message_mentions (
+    message_id bigint NOT NULL REFERENCES messages(id),
+    user_id bigint NOT NULL REFERENCES users(id),
+    kind mention_kind NOT NULL,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (message_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_mentions_user_id ON message_mentions(user_id, message_id DESC);
+
+-- notify the mentioned user
+CREATE OR REPLACE FUNCTION mention_created()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    msg messages;
+BEGIN
+    SELECT * INTO msg FROM messages WHERE id = NEW.message_id;
+
+    RAISE NOTICE 'mention_created: %, %', NEW.message_id, NEW.user_id;
+    PERFORM pg_notify('mention_created', json_build_object('mention', json_build_object('chat_id', msg.chat_id,
+        'message_id', msg.id, 'sender_id', msg.sender_id, 'user_id', NEW.user_id, 'kind', NEW.kind))::text);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER mention_created_trigger
+    AFTER INSERT ON message_mentions
+    FOR EACH ROW
+    EXECUTE FUNCTION mention_created();
@@ -0,0 +1,11 @@
+-- read position of every member in a chat
+CREATE TABLE IF NOT EXISTS chat_reads (
+    chat_id bigint NOT NULL REFERENCES chats(id),
+    user_id bigint NOT NULL REFERENCES users(id),
+    last_read_message_id bigint NOT NULL DEFAULT 0,
+    updated_at timestamptz DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+-- count the messages after a read position
+CREATE INDEX IF NOT EXISTS idx_messages_chat_id_id ON messages(chat_id, id);
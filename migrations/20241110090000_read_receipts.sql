@@ -0,0 +1,24 @@
+-- notify read positions together with the chat type and members, for read receipts
+CREATE OR REPLACE FUNCTION chat_read_updated()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    chat chats;
+BEGIN
+    IF TG_OP = 'UPDATE' AND OLD.last_read_message_id = NEW.last_read_message_id THEN
+        RETURN NULL;
+    END IF;
+
+    SELECT * INTO chat FROM chats WHERE id = NEW.chat_id;
+
+    RAISE NOTICE 'chat_read_updated: %, %', NEW.chat_id, NEW.user_id;
+    PERFORM pg_notify('chat_read_updated', json_build_object('read', NEW, 'type', chat.type, 'members', chat.members)::text);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER chat_read_updated_trigger
+    AFTER INSERT OR UPDATE ON chat_reads
+    FOR EACH ROW
+    EXECUTE FUNCTION chat_read_updated();
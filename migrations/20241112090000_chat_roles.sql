@@ -0,0 +1,21 @@
+-- elevated roles of chat members, everyone else in chats.members is a plain member
+CREATE TYPE chat_role AS ENUM (
+    'owner',
+    'admin',
+    'member'
+);
+
+CREATE TABLE IF NOT EXISTS chat_roles (
+    chat_id bigint NOT NULL REFERENCES chats(id),
+    user_id bigint NOT NULL REFERENCES users(id),
+    role chat_role NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+-- a chat has at most one owner
+CREATE UNIQUE INDEX IF NOT EXISTS chat_roles_owner_idx ON chat_roles(chat_id) WHERE role = 'owner';
+
+-- existing chats are owned by their first member
+INSERT INTO chat_roles (chat_id, user_id, role)
+SELECT id, members[1], 'owner' FROM chats WHERE cardinality(members) > 0
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,5 @@
+-- messages pinned to the top of their chat
+ALTER TABLE messages ADD COLUMN pinned_at timestamptz;
+ALTER TABLE messages ADD COLUMN pinned_by bigint REFERENCES users(id);
+
+CREATE INDEX IF NOT EXISTS messages_pinned_idx ON messages(chat_id, pinned_at) WHERE pinned_at IS NOT NULL;
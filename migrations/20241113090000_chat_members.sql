@@ -0,0 +1,132 @@
+-- chat membership as rows instead of the chats.members array, with per-member data
+CREATE TABLE IF NOT EXISTS chat_members (
+    chat_id bigint NOT NULL REFERENCES chats(id),
+    user_id bigint NOT NULL REFERENCES users(id),
+    role chat_role NOT NULL DEFAULT 'member',
+    joined_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+-- all chats of a user
+CREATE INDEX IF NOT EXISTS chat_members_user_id_idx ON chat_members(user_id, chat_id);
+
+-- a chat has at most one owner
+CREATE UNIQUE INDEX IF NOT EXISTS chat_members_owner_idx ON chat_members(chat_id) WHERE role = 'owner';
+
+-- the array had no foreign key, skip members whose user is gone
+INSERT INTO chat_members (chat_id, user_id, role, joined_at)
+SELECT chats.id, users.id, COALESCE(chat_roles.role, 'member'), COALESCE(chats.created_at, CURRENT_TIMESTAMP)
+FROM chats
+CROSS JOIN LATERAL unnest(chats.members) AS m(user_id)
+JOIN users ON users.id = m.user_id
+LEFT JOIN chat_roles ON chat_roles.chat_id = chats.id AND chat_roles.user_id = m.user_id
+ON CONFLICT DO NOTHING;
+
+DROP TABLE chat_roles;
+ALTER TABLE chats DROP COLUMN members;
+
+-- member ids of a chat, the longest standing first
+CREATE OR REPLACE FUNCTION chat_member_ids(chat bigint)
+    RETURNS bigint[]
+    AS $$
+    SELECT ARRAY(SELECT user_id FROM chat_members WHERE chat_id = chat ORDER BY joined_at, user_id);
+$$
+LANGUAGE sql STABLE;
+
+-- a chat as notify_server and the api know it, with its members
+CREATE OR REPLACE FUNCTION chat_json(chat chats)
+    RETURNS jsonb
+    AS $$
+    SELECT to_jsonb(chat) || jsonb_build_object('members', chat_member_ids(chat.id));
+$$
+LANGUAGE sql STABLE;
+
+-- membership changes, and so creating and deleting chats, are notified from chat_members
+DROP TRIGGER IF EXISTS chat_updated_trigger ON chats;
+
+-- member changes touch the chats row without changing it, they are notified on their own
+CREATE OR REPLACE TRIGGER chat_updated_trigger
+    AFTER UPDATE ON chats
+    FOR EACH ROW
+    WHEN (OLD.* IS DISTINCT FROM NEW.*)
+    EXECUTE FUNCTION chat_updated();
+
+-- notify the users who joined or left a chat, once per chat and statement
+CREATE OR REPLACE FUNCTION chat_members_updated()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    rec record;
+    chat chats;
+BEGIN
+    FOR rec IN
+        SELECT changed.chat_id, array_agg(changed.user_id) AS user_ids FROM changed GROUP BY changed.chat_id
+    LOOP
+        SELECT * INTO chat FROM chats WHERE id = rec.chat_id;
+        CONTINUE WHEN NOT FOUND;
+
+        RAISE NOTICE 'chat_members_updated: %, %', TG_OP, rec.chat_id;
+        PERFORM pg_notify('chat_members_updated', json_build_object('op', TG_OP, 'chat', chat_json(chat), 'user_ids', rec.user_ids)::text);
+    END LOOP;
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+-- a trigger with a transition table only handles one kind of event
+CREATE OR REPLACE TRIGGER chat_members_inserted_trigger
+    AFTER INSERT ON chat_members
+    REFERENCING NEW TABLE AS changed
+    FOR EACH STATEMENT
+    EXECUTE FUNCTION chat_members_updated();
+
+CREATE OR REPLACE TRIGGER chat_members_deleted_trigger
+    AFTER DELETE ON chat_members
+    REFERENCING OLD TABLE AS changed
+    FOR EACH STATEMENT
+    EXECUTE FUNCTION chat_members_updated();
+
+-- the triggers below read the members from chat_members now
+CREATE OR REPLACE FUNCTION reaction_updated()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    r message_reactions;
+    chat bigint;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        r := OLD;
+    ELSE
+        r := NEW;
+    END IF;
+
+    SELECT chat_id INTO chat FROM messages WHERE id = r.message_id;
+
+    RAISE NOTICE 'reaction_updated: %, %', TG_OP, r.message_id;
+    PERFORM pg_notify('reaction_updated', json_build_object('op', TG_OP,
+        'reaction', json_build_object('chat_id', chat, 'message_id', r.message_id, 'user_id', r.user_id, 'emoji', r.emoji),
+        'members', chat_member_ids(chat))::text);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION chat_read_updated()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    chat chats;
+BEGIN
+    IF TG_OP = 'UPDATE' AND OLD.last_read_message_id = NEW.last_read_message_id THEN
+        RETURN NULL;
+    END IF;
+
+    SELECT * INTO chat FROM chats WHERE id = NEW.chat_id;
+
+    RAISE NOTICE 'chat_read_updated: %, %', NEW.chat_id, NEW.user_id;
+    PERFORM pg_notify('chat_read_updated', json_build_object('read', NEW, 'type', chat.type,
+        'members', chat_member_ids(NEW.chat_id))::text);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
@@ -0,0 +1,14 @@
+-- invite links to private channels and groups
+CREATE TABLE IF NOT EXISTS chat_invites (
+    id bigserial PRIMARY KEY,
+    chat_id bigint NOT NULL REFERENCES chats(id),
+    code varchar(64) NOT NULL UNIQUE,
+    created_by bigint NOT NULL REFERENCES users(id),
+    expires_at timestamptz,
+    max_uses int,
+    uses int NOT NULL DEFAULT 0,
+    revoked_at timestamptz,
+    created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS chat_invites_chat_id_idx ON chat_invites(chat_id);
@@ -0,0 +1,2 @@
+-- archived chats are read-only and hidden from the chat list
+ALTER TABLE chats ADD COLUMN archived_at timestamptz;
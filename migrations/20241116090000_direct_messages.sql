@@ -0,0 +1,26 @@
+-- the member pair of a direct message, the lower id first; a note to self has the same user twice
+ALTER TABLE chats
+    ADD COLUMN dm_user_low bigint REFERENCES users(id),
+    ADD COLUMN dm_user_high bigint REFERENCES users(id),
+    ADD CONSTRAINT chats_dm_pair_check CHECK (dm_user_low <= dm_user_high);
+
+-- unnamed chats were inserted with a NULL name and failed, they are stored with an empty one
+ALTER TABLE chats ALTER COLUMN name SET DEFAULT '';
+
+-- existing duplicates keep no pair, only the oldest direct message of two users is reused
+UPDATE chats SET dm_user_low = pairs.low, dm_user_high = pairs.high
+FROM (
+    SELECT DISTINCT ON (low, high) id, low, high
+    FROM (
+        SELECT chats.id, min(chat_members.user_id) AS low, max(chat_members.user_id) AS high
+        FROM chats
+        JOIN chat_members ON chat_members.chat_id = chats.id
+        WHERE chats.type = 'single'
+        GROUP BY chats.id
+    ) AS dms
+    ORDER BY low, high, id
+) AS pairs
+WHERE chats.id = pairs.id;
+
+-- at most one direct message per pair of users
+CREATE UNIQUE INDEX IF NOT EXISTS chats_dm_pair_idx ON chats(dm_user_low, dm_user_high);
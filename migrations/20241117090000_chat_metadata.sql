@@ -0,0 +1,39 @@
+-- what a chat is about and who started it
+ALTER TABLE chats
+    ADD COLUMN topic varchar(250),
+    ADD COLUMN description text,
+    ADD COLUMN avatar text,
+    ADD COLUMN created_by bigint REFERENCES users(id),
+    ADD COLUMN updated_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+-- the creator became the owner, unless the ownership has been handed over since
+UPDATE chats SET created_by = chat_members.user_id, updated_at = COALESCE(chats.created_at, CURRENT_TIMESTAMP)
+FROM chat_members
+WHERE chat_members.chat_id = chats.id AND chat_members.role = 'owner';
+
+CREATE OR REPLACE FUNCTION chat_touched()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    NEW.updated_at := CURRENT_TIMESTAMP;
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+-- member changes touch the chats row without changing it, they don't count
+CREATE OR REPLACE TRIGGER chat_touched_trigger
+    BEFORE UPDATE ON chats
+    FOR EACH ROW
+    WHEN (OLD.* IS DISTINCT FROM NEW.*)
+    EXECUTE FUNCTION chat_touched();
+
+-- system messages record changes to the chat in its timeline
+CREATE TYPE message_kind AS ENUM ('user', 'system');
+
+ALTER TABLE messages ADD COLUMN kind message_kind NOT NULL DEFAULT 'user';
+-- system messages have no sender, who did what is in their payload
+ALTER TABLE messages
+    ALTER COLUMN sender_id DROP NOT NULL,
+    ADD COLUMN payload jsonb,
+    ADD CONSTRAINT messages_sender_check CHECK (kind = 'system' OR sender_id IS NOT NULL);
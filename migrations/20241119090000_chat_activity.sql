@@ -0,0 +1,3 @@
+-- the latest message of each chat for the chat list, replies and tombstones left out
+CREATE INDEX IF NOT EXISTS idx_messages_chat_timeline ON messages(chat_id, id DESC)
+    WHERE parent_id IS NULL AND deleted_at IS NULL;
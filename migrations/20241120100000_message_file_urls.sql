@@ -0,0 +1,11 @@
+-- messages stored their files as paths on disk, they keep the url the upload returned
+-- now; paths that don't point at an upload of the chat's workspace are dropped
+UPDATE messages SET files = ARRAY(
+    SELECT regexp_replace(f.path, '^.*/([0-9]+/[0-9a-f]{3}/[0-9a-f]{3}/[0-9a-f]{34}\.[^/]+)$', '/files/\1')
+    FROM unnest(messages.files) WITH ORDINALITY AS f(path, n)
+    WHERE f.path ~ ('/' || chats.ws_id || '/[0-9a-f]{3}/[0-9a-f]{3}/[0-9a-f]{34}\.[^/]+$')
+        AND f.path !~ '/\.\./'
+    ORDER BY f.n
+)
+FROM chats
+WHERE chats.id = messages.chat_id AND cardinality(messages.files) > 0;
@@ -0,0 +1,70 @@
+-- like chats and messages, membership, reactions and reads notify ids only; notify_server
+-- resolves the members from chat_members. The user ids of a statement are sent in chunks,
+-- a channel everyone joins at once would not fit into one payload
+CREATE OR REPLACE FUNCTION chat_members_updated()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    rec record;
+    new_chat boolean;
+    i integer;
+BEGIN
+    FOR rec IN
+        SELECT changed.chat_id, array_agg(changed.user_id ORDER BY changed.user_id) AS user_ids FROM changed GROUP BY changed.chat_id
+    LOOP
+        CONTINUE WHEN NOT EXISTS (SELECT 1 FROM chats WHERE id = rec.chat_id);
+
+        -- the statement added every member, the chat is new to all of them
+        new_chat := TG_OP = 'INSERT' AND NOT EXISTS (
+            SELECT 1 FROM chat_members WHERE chat_id = rec.chat_id AND user_id <> ALL(rec.user_ids));
+
+        RAISE NOTICE 'chat_members_updated: %, %', TG_OP, rec.chat_id;
+        FOR i IN 1..cardinality(rec.user_ids) BY 300 LOOP
+            PERFORM pg_notify('chat_members_updated', json_build_object('op', TG_OP, 'chat_id', rec.chat_id,
+                'user_ids', rec.user_ids[i:i + 299], 'new_chat', new_chat)::text);
+        END LOOP;
+    END LOOP;
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION reaction_updated()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    r message_reactions;
+    chat bigint;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        r := OLD;
+    ELSE
+        r := NEW;
+    END IF;
+
+    SELECT chat_id INTO chat FROM messages WHERE id = r.message_id;
+
+    RAISE NOTICE 'reaction_updated: %, %', TG_OP, r.message_id;
+    PERFORM pg_notify('reaction_updated', json_build_object('op', TG_OP,
+        'reaction', json_build_object('chat_id', chat, 'message_id', r.message_id, 'user_id', r.user_id, 'emoji', r.emoji))::text);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION chat_read_updated()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' AND OLD.last_read_message_id = NEW.last_read_message_id THEN
+        RETURN NULL;
+    END IF;
+
+    RAISE NOTICE 'chat_read_updated: %, %', NEW.chat_id, NEW.user_id;
+    PERFORM pg_notify('chat_read_updated', json_build_object('read', NEW)::text);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+DROP FUNCTION IF EXISTS chat_json(chats);
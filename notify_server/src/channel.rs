@@ -0,0 +1,195 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chat_core::Presence;
+use tokio::sync::broadcast;
+
+use crate::AppEvent;
+
+const CHANNEL_CAPACITY: usize = 256;
+// how many events are kept per user for Last-Event-ID replay
+const REPLAY_CAPACITY: usize = 1000;
+// how long a disconnected user's events are kept around
+const REPLAY_TTL: Duration = Duration::from_secs(5 * 60);
+// a connected user without heartbeat for this long is away
+const AWAY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug)]
+pub struct UserEvent {
+    // ephemeral events have no id, so they don't move the client's Last-Event-ID
+    pub id: Option<u64>,
+    pub event: Arc<AppEvent>,
+}
+
+// all connections of a user share one broadcast channel, plus the recent
+// events so a reconnecting client can catch up with Last-Event-ID
+pub(crate) struct UserChannel {
+    pub(crate) ws_id: i64,
+    tx: broadcast::Sender<Arc<UserEvent>>,
+    history: VecDeque<(u64, Arc<UserEvent>)>,
+    // every event newer than this id was recorded (it may be evicted later)
+    since: u64,
+    last_active: Instant,
+    last_heartbeat: Instant,
+    presence: Presence,
+}
+
+impl UserChannel {
+    pub(crate) fn new(ws_id: i64, since: u64) -> Self {
+        Self {
+            ws_id,
+            tx: broadcast::channel(CHANNEL_CAPACITY).0,
+            history: VecDeque::new(),
+            since,
+            last_active: Instant::now(),
+            last_heartbeat: Instant::now(),
+            presence: Presence::Offline,
+        }
+    }
+
+    pub(crate) fn subscribe(&mut self) -> broadcast::Receiver<Arc<UserEvent>> {
+        self.last_active = Instant::now();
+        self.last_heartbeat = Instant::now();
+        self.tx.subscribe()
+    }
+
+    pub(crate) fn is_connected(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    pub(crate) fn heartbeat(&mut self) {
+        self.last_heartbeat = Instant::now();
+    }
+
+    /// Re-evaluate the presence from the live connections and heartbeats, returns it if changed.
+    pub(crate) fn update_presence(&mut self) -> Option<Presence> {
+        let presence = if !self.is_connected() {
+            Presence::Offline
+        } else if self.last_heartbeat.elapsed() > AWAY_AFTER {
+            Presence::Away
+        } else {
+            Presence::Online
+        };
+
+        if presence == self.presence {
+            return None;
+        }
+        self.presence = presence;
+        Some(presence)
+    }
+
+    pub(crate) fn push(&mut self, event: Arc<UserEvent>) {
+        if let Some(id) = event.id {
+            if self.history.len() == REPLAY_CAPACITY {
+                if let Some((evicted, _)) = self.history.pop_front() {
+                    self.since = evicted;
+                }
+            }
+            self.history.push_back((id, event.clone()));
+        }
+
+        if self.tx.send(event).is_ok() {
+            self.last_active = Instant::now();
+        }
+    }
+
+    /// Events after `last_id`, or a single ResyncRequired event if some of them were evicted.
+    pub(crate) fn replay(&self, last_id: u64) -> Vec<Arc<UserEvent>> {
+        if last_id < self.since {
+            let id = self.history.back().map_or(self.since, |(id, _)| *id);
+            return vec![Arc::new(UserEvent {
+                id: Some(id),
+                event: Arc::new(AppEvent::ResyncRequired),
+            })];
+        }
+
+        self.history
+            .iter()
+            .filter(|(id, _)| *id > last_id)
+            .map(|(_, e)| e.clone())
+            .collect()
+    }
+
+    pub(crate) fn is_expired(&self) -> bool {
+        !self.is_connected() && self.last_active.elapsed() > REPLAY_TTL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_event(id: u64) -> Arc<UserEvent> {
+        Arc::new(UserEvent {
+            id: Some(id),
+            event: Arc::new(AppEvent::ResyncRequired),
+        })
+    }
+
+    fn ids(events: &[Arc<UserEvent>]) -> Vec<u64> {
+        events.iter().filter_map(|e| e.id).collect()
+    }
+
+    #[test]
+    fn test_replay_should_return_missed_events() {
+        let mut ch = UserChannel::new(1, 10);
+        for id in 11..=15 {
+            ch.push(user_event(id));
+        }
+
+        assert_eq!(ids(&ch.replay(12)), vec![13, 14, 15]);
+        assert_eq!(ids(&ch.replay(10)), vec![11, 12, 13, 14, 15]);
+        assert!(ch.replay(15).is_empty());
+    }
+
+    #[test]
+    fn test_replay_should_require_resync_when_gap_is_too_old() {
+        let mut ch = UserChannel::new(1, 0);
+        for id in 1..=(REPLAY_CAPACITY as u64 + 2) {
+            ch.push(user_event(id));
+        }
+
+        // events 1 and 2 were evicted
+        assert_eq!(ch.replay(2).len(), REPLAY_CAPACITY);
+
+        let events = ch.replay(1);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event.as_ref(), &AppEvent::ResyncRequired);
+        assert_eq!(events[0].id, Some(REPLAY_CAPACITY as u64 + 2));
+    }
+
+    #[test]
+    fn test_ephemeral_events_should_not_be_replayed() {
+        let mut ch = UserChannel::new(1, 0);
+        ch.push(user_event(1));
+        ch.push(Arc::new(UserEvent {
+            id: None,
+            event: Arc::new(AppEvent::ResyncRequired),
+        }));
+        ch.push(user_event(2));
+
+        assert_eq!(ch.replay(0).len(), 2);
+    }
+
+    #[test]
+    fn test_presence_should_follow_connections() {
+        let mut ch = UserChannel::new(1, 0);
+        assert_eq!(ch.update_presence(), None);
+
+        let rx = ch.subscribe();
+        assert_eq!(ch.update_presence(), Some(Presence::Online));
+        assert_eq!(ch.update_presence(), None);
+
+        ch.last_heartbeat = Instant::now() - AWAY_AFTER - Duration::from_secs(1);
+        assert_eq!(ch.update_presence(), Some(Presence::Away));
+
+        ch.heartbeat();
+        assert_eq!(ch.update_presence(), Some(Presence::Online));
+
+        drop(rx);
+        assert_eq!(ch.update_presence(), Some(Presence::Offline));
+    }
+}
@@ -0,0 +1,78 @@
+use chat_core::{Chat, ChatRead, Message};
+
+use crate::{error::AppError, AppState};
+
+impl AppState {
+    /// The chat with its members, the notifications only carry its id.
+    pub(crate) async fn get_chat(&self, chat_id: u64) -> Result<Option<Chat>, AppError> {
+        let chat = sqlx::query_as(
+            r#"
+            SELECT id, ws_id, name, type, chat_member_ids(id) AS members, created_at, archived_at,
+                topic, description, avatar, created_by, updated_at
+            FROM chats WHERE id = $1
+            "#,
+        )
+        .bind(chat_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(chat)
+    }
+
+    pub(crate) async fn get_message(&self, message_id: u64) -> Result<Option<Message>, AppError> {
+        let message = sqlx::query_as(
+            r#"
+            SELECT id, chat_id, sender_id, content, files, created_at, updated_at, edited, deleted_at,
+                parent_id, reply_count, last_reply_at, pinned_at, pinned_by, kind, payload
+            FROM messages WHERE id = $1
+            "#,
+        )
+        .bind(message_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(message)
+    }
+
+    pub(crate) async fn chat_member_ids(&self, chat_id: u64) -> Result<Vec<i64>, AppError> {
+        let members: Vec<(i64,)> =
+            sqlx::query_as("SELECT user_id FROM chat_members WHERE chat_id=$1")
+                .bind(chat_id as i64)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(members.into_iter().map(|(id,)| id).collect())
+    }
+
+    /// Members of the chat, as long as the user is one of them.
+    pub(crate) async fn get_chat_members(
+        &self,
+        chat_id: u64,
+        user_id: u64,
+    ) -> Result<Vec<i64>, AppError> {
+        let members = self.chat_member_ids(chat_id).await?;
+        if !members.contains(&(user_id as i64)) {
+            return Err(AppError::NotChatMember(user_id, chat_id));
+        }
+        Ok(members)
+    }
+
+    /// Same as `POST /api/chats/:id/read` of chat_server.
+    pub(crate) async fn mark_read(
+        &self,
+        chat_id: u64,
+        user_id: u64,
+        message_id: u64,
+    ) -> Result<(), AppError> {
+        self.get_chat_members(chat_id, user_id).await?;
+
+        let read = ChatRead::mark(
+            &self.pool,
+            chat_id as i64,
+            user_id as i64,
+            message_id as i64,
+        )
+        .await?;
+        if read.is_none() {
+            return Err(AppError::NotFound(format!("message {}", message_id)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+use std::{env, fs::File};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ServerConfig {
+    pub port: u16,
+    pub db_url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuthConfig {
+    pub public_key: String,
+}
+
+impl AppConfig {
+    pub fn load() -> anyhow::Result<Self> {
+        let cfg = match (
+            File::open("notify.yml"),
+            File::open("/etc/chat/notify.yml"),
+            env::var("NOTIFY_CONFIG"),
+        ) {
+            (Ok(f), _, _) => serde_yaml::from_reader(f),
+            (_, Ok(f), _) => serde_yaml::from_reader(f),
+            (_, _, Ok(f)) => serde_yaml::from_reader(File::open(f)?),
+            _ => anyhow::bail!("Could not find notify.yml"),
+        };
+
+        anyhow::Ok(cfg?)
+    }
+}
@@ -0,0 +1,31 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use thiserror::Error;
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("jwt error: {0}")]
+    JwtError(#[from] jwt_simple::Error),
+
+    #[error("sql error: {0}")]
+    SqlxError(#[from] sqlx::Error),
+
+    #[error("user {0} is not a member of chat {1}")]
+    NotChatMember(u64, u64),
+
+    #[error("{0} not found")]
+    NotFound(String),
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> axum::response::Response {
+        let status_code = match self {
+            AppError::JwtError(_) => StatusCode::FORBIDDEN,
+            AppError::SqlxError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::NotChatMember(_, _) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+        };
+
+        (status_code, format!("{:?}", self)).into_response()
+    }
+}
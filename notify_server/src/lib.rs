@@ -1,18 +1,204 @@
+mod channel;
+mod chat;
+mod config;
+mod error;
+mod notif;
+mod presence;
 mod sse;
+mod typing;
+mod ws;
 
+use std::{
+    collections::HashSet,
+    fmt,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
 use axum::{
+    middleware::from_fn_with_state,
     response::{Html, IntoResponse},
-    routing::get,
+    routing::{get, post},
     Router,
 };
+use channel::{UserChannel, UserEvent};
+use chat_core::{
+    middlewares::jwt::{verify_token, TokenVerify},
+    utils::DecodingKey,
+    User,
+};
+use dashmap::DashMap;
+use error::AppError;
+use futures::{stream, Stream};
+use presence::heartbeat_handler;
+use sqlx::PgPool;
 use sse::sse_handler;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tracing::{info, warn};
+use typing::{setup_typing_expiry, typing_handler, TypingTracker};
+use ws::ws_handler;
+
+pub use config::AppConfig;
+pub use notif::{setup_pg_listener, AppEvent};
 
 const INDEX_HTML: &str = include_str!("../index.html");
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(10);
 
-pub fn get_router() -> Router {
-    Router::new()
-        .route("/", get(index_handler))
+pub(crate) type UserMap = Arc<DashMap<u64, UserChannel>>;
+
+#[derive(Debug, Clone)]
+pub struct AppState {
+    inner: Arc<AppStateInner>,
+}
+
+pub struct AppStateInner {
+    pub config: AppConfig,
+    pool: PgPool,
+    dk: DecodingKey,
+    users: UserMap,
+    typing: TypingTracker,
+    // id of the last event, seeded with the start time so ids keep growing across restarts
+    event_id: AtomicU64,
+}
+
+impl Deref for AppState {
+    type Target = AppStateInner;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl AppState {
+    pub async fn try_new(config: AppConfig) -> anyhow::Result<Self> {
+        let dk = DecodingKey::load(&config.auth.public_key).context("load DecodingKey failed")?;
+        let pool = PgPool::connect(&config.server.db_url)
+            .await
+            .context("connect DB failed")?;
+        Ok(Self {
+            inner: Arc::new(AppStateInner {
+                config,
+                pool,
+                dk,
+                users: Arc::new(DashMap::new()),
+                typing: TypingTracker::default(),
+                event_id: AtomicU64::new(now_micros()),
+            }),
+        })
+    }
+
+    /// The user's events, starting with the ones missed since `last_event_id`.
+    pub(crate) fn event_stream(
+        &self,
+        user: &User,
+        last_event_id: Option<u64>,
+    ) -> impl Stream<Item = Arc<UserEvent>> {
+        let user_id = user.id as u64;
+
+        // hold the entry lock so no event slips in between the replay and the subscription
+        let mut ch = self
+            .users
+            .entry(user_id)
+            .or_insert_with(|| UserChannel::new(user.ws_id, self.event_id.load(Ordering::SeqCst)));
+
+        let replay = last_event_id.map(|id| ch.replay(id)).unwrap_or_default();
+        let rx = ch.subscribe();
+
+        // a lagged client is disconnected, it will reconnect with Last-Event-ID and catch up
+        let live = BroadcastStream::new(rx)
+            .take_while(move |v| match v {
+                Ok(_) => true,
+                Err(e) => {
+                    warn!("user {} lagged behind: {}", user_id, e);
+                    false
+                }
+            })
+            .filter_map(|v| v.ok());
+
+        stream::iter(replay).chain(live)
+    }
+
+    pub(crate) fn notify(&self, user_ids: &HashSet<u64>, event: Arc<AppEvent>) {
+        let id = if event.is_ephemeral() {
+            None
+        } else {
+            Some(self.event_id.fetch_add(1, Ordering::SeqCst) + 1)
+        };
+        let event = Arc::new(UserEvent { id, event });
+
+        for user_id in user_ids {
+            if let Some(mut ch) = self.users.get_mut(user_id) {
+                ch.push(event.clone());
+            }
+        }
+    }
+
+    fn cleanup_users(&self) {
+        let before = self.users.len();
+        self.users.retain(|_, ch| !ch.is_expired());
+        let removed = before - self.users.len();
+        if removed > 0 {
+            info!("removed {} disconnected users", removed);
+        }
+    }
+}
+
+impl fmt::Debug for AppStateInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppStateInner")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl TokenVerify for AppState {
+    type Error = AppError;
+    fn verify(&self, token: &str) -> Result<User, Self::Error> {
+        Ok(self.dk.verify(token)?)
+    }
+}
+
+pub async fn get_router(config: AppConfig) -> anyhow::Result<Router> {
+    let state = AppState::try_new(config).await?;
+    state.reset_presences().await?;
+    setup_pg_listener(state.clone()).await?;
+    setup_cleanup(state.clone());
+    setup_typing_expiry(state.clone());
+
+    let app = Router::new()
         .route("/events", get(sse_handler))
+        .route("/ws", get(ws_handler))
+        .route("/heartbeat", post(heartbeat_handler))
+        .route("/chats/:id/typing", post(typing_handler))
+        .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
+        .route("/", get(index_handler))
+        .with_state(state);
+
+    Ok(app)
+}
+
+fn setup_cleanup(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            // publish the users going offline before their channels are dropped
+            if let Err(e) = state.refresh_all_presences().await {
+                warn!("refresh presences failed: {}", e);
+            }
+            state.cleanup_users();
+        }
+    });
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as u64)
 }
 
 async fn index_handler() -> impl IntoResponse {
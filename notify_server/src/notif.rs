@@ -0,0 +1,566 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use chat_core::{Chat, ChatRead, ChatType, MentionKind, Message, Presence};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use tracing::{info, warn};
+
+use crate::AppState;
+
+const CHAT_UPDATED: &str = "chat_updated";
+const CHAT_MEMBERS_UPDATED: &str = "chat_members_updated";
+const MESSAGE_UPDATED: &str = "message_updated";
+const REACTION_UPDATED: &str = "reaction_updated";
+const MENTION_CREATED: &str = "mention_created";
+const CHAT_READ_UPDATED: &str = "chat_read_updated";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "event")]
+pub enum AppEvent {
+    NewChat(Chat),
+    UpdateChat(Chat),
+    AddToChat(Chat),
+    RemoveFromChat(Chat),
+    // the chat was purged, there is nothing left to send but its id
+    DeleteChat(DeletedChat),
+    NewMessage(Message),
+    UpdateMessage(Message),
+    DeleteMessage(Message),
+    // the events a client missed are gone, it has to reload its state
+    ResyncRequired,
+    Typing(ChatTyping),
+    MessageRead(MessageRead),
+    Presence(UserPresence),
+    AddReaction(ChatReaction),
+    RemoveReaction(ChatReaction),
+    Mention(ChatMention),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DeletedChat {
+    pub chat_id: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatTyping {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub typing: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UserPresence {
+    pub user_id: i64,
+    pub presence: Presence,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MessageRead {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub message_id: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatReaction {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub user_id: i64,
+    pub emoji: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatMention {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub sender_id: i64,
+    // the mentioned user
+    pub user_id: i64,
+    pub kind: MentionKind,
+}
+
+#[derive(Debug)]
+struct Notification {
+    // the users who should receive the event
+    user_ids: HashSet<u64>,
+    event: Arc<AppEvent>,
+}
+
+// payload of pg_notify('chat_updated', ...)
+#[derive(Debug, Deserialize)]
+struct ChatUpdated {
+    op: String,
+    chat_id: i64,
+}
+
+// payload of pg_notify('chat_members_updated', ...)
+#[derive(Debug, Deserialize)]
+struct ChatMembersUpdated {
+    op: String,
+    chat_id: i64,
+    // the users who joined or left the chat, a big statement is notified in chunks
+    user_ids: Vec<i64>,
+    // the statement added all the members of the chat
+    new_chat: bool,
+}
+
+// payload of pg_notify('message_updated', ...)
+#[derive(Debug, Deserialize)]
+struct MessageUpdated {
+    op: String,
+    chat_id: i64,
+    message_id: i64,
+}
+
+// payload of pg_notify('reaction_updated', ...)
+#[derive(Debug, Deserialize)]
+struct ReactionUpdated {
+    op: String,
+    reaction: ChatReaction,
+}
+
+// payload of pg_notify('chat_read_updated', ...)
+#[derive(Debug, Deserialize)]
+struct ChatReadUpdated {
+    read: ChatRead,
+}
+
+// payload of pg_notify('mention_created', ...)
+#[derive(Debug, Deserialize)]
+struct MentionCreated {
+    mention: ChatMention,
+}
+
+impl AppEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppEvent::NewChat(_) => "NewChat",
+            AppEvent::UpdateChat(_) => "UpdateChat",
+            AppEvent::AddToChat(_) => "AddToChat",
+            AppEvent::RemoveFromChat(_) => "RemoveFromChat",
+            AppEvent::DeleteChat(_) => "DeleteChat",
+            AppEvent::NewMessage(_) => "NewMessage",
+            AppEvent::UpdateMessage(_) => "UpdateMessage",
+            AppEvent::DeleteMessage(_) => "DeleteMessage",
+            AppEvent::ResyncRequired => "ResyncRequired",
+            AppEvent::Typing(_) => "Typing",
+            AppEvent::MessageRead(_) => "MessageRead",
+            AppEvent::Presence(_) => "Presence",
+            AppEvent::AddReaction(_) => "AddReaction",
+            AppEvent::RemoveReaction(_) => "RemoveReaction",
+            AppEvent::Mention(_) => "Mention",
+        }
+    }
+
+    /// Ephemeral events are only delivered to live connections, they are never replayed.
+    /// A reconnecting client gets the current presences from `/api/users`.
+    pub fn is_ephemeral(&self) -> bool {
+        matches!(self, AppEvent::Typing(_) | AppEvent::Presence(_))
+    }
+}
+
+pub async fn setup_pg_listener(state: AppState) -> Result<()> {
+    let mut listener = PgListener::connect(&state.config.server.db_url).await?;
+    listener.listen(CHAT_UPDATED).await?;
+    listener.listen(MESSAGE_UPDATED).await?;
+    listener.listen(CHAT_MEMBERS_UPDATED).await?;
+    listener.listen(REACTION_UPDATED).await?;
+    listener.listen(MENTION_CREATED).await?;
+    listener.listen(CHAT_READ_UPDATED).await?;
+
+    tokio::spawn(async move {
+        loop {
+            // recv() re-connects and re-listens by itself if the connection is lost
+            let notif = match listener.recv().await {
+                Ok(notif) => notif,
+                Err(e) => {
+                    warn!("receive pg notification failed: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            info!("received notification: {:?}", notif);
+            let notifications = match state
+                .load_notifications(notif.channel(), notif.payload())
+                .await
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("load notification failed: {}", e);
+                    continue;
+                }
+            };
+
+            for notification in notifications {
+                state.notify(&notification.user_ids, notification.event);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+impl AppState {
+    // the payloads only carry ids, the rows are loaded once they are committed
+    async fn load_notifications(&self, channel: &str, payload: &str) -> Result<Vec<Notification>> {
+        match channel {
+            CHAT_UPDATED => {
+                let payload: ChatUpdated = serde_json::from_str(payload)?;
+                let chat = self.get_chat(payload.chat_id as u64).await?;
+                Ok(chat
+                    .and_then(|chat| load_chat_notification(&payload.op, chat))
+                    .into_iter()
+                    .collect())
+            }
+            CHAT_MEMBERS_UPDATED => {
+                let payload: ChatMembersUpdated = serde_json::from_str(payload)?;
+                let chat = self.get_chat(payload.chat_id as u64).await?;
+                Ok(load_chat_members_notifications(payload, chat))
+            }
+            MESSAGE_UPDATED => {
+                let payload: MessageUpdated = serde_json::from_str(payload)?;
+                let Some(message) = self.get_message(payload.message_id as u64).await? else {
+                    return Ok(vec![]);
+                };
+                let members = self.chat_member_ids(payload.chat_id as u64).await?;
+                Ok(load_message_notification(&payload.op, message, &members)
+                    .into_iter()
+                    .collect())
+            }
+            REACTION_UPDATED => {
+                let payload: ReactionUpdated = serde_json::from_str(payload)?;
+                let members = self
+                    .chat_member_ids(payload.reaction.chat_id as u64)
+                    .await?;
+                Ok(
+                    load_reaction_notification(&payload.op, payload.reaction, &members)
+                        .into_iter()
+                        .collect(),
+                )
+            }
+            CHAT_READ_UPDATED => {
+                let payload: ChatReadUpdated = serde_json::from_str(payload)?;
+                let Some(chat) = self.get_chat(payload.read.chat_id as u64).await? else {
+                    return Ok(vec![]);
+                };
+                Ok(load_read_notification(payload.read, &chat)
+                    .into_iter()
+                    .collect())
+            }
+            MENTION_CREATED => {
+                let payload: MentionCreated = serde_json::from_str(payload)?;
+                Ok(load_mention_notification(payload.mention))
+            }
+            _ => anyhow::bail!("unknown channel: {}", channel),
+        }
+    }
+}
+
+impl Notification {
+    fn new(user_ids: HashSet<u64>, event: AppEvent) -> Option<Self> {
+        if user_ids.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            user_ids,
+            event: Arc::new(event),
+        })
+    }
+}
+
+// joining and leaving are notified from chat_members, this is the chat itself changing
+fn load_chat_notification(op: &str, chat: Chat) -> Option<Notification> {
+    match op {
+        "UPDATE" => Notification::new(user_ids(&chat.members), AppEvent::UpdateChat(chat)),
+        op => {
+            warn!("unexpected chat operation: {}", op);
+            None
+        }
+    }
+}
+
+// a chat whose members all joined at once is new to them
+fn load_chat_members_notifications(
+    payload: ChatMembersUpdated,
+    chat: Option<Chat>,
+) -> Vec<Notification> {
+    let changed = user_ids(&payload.user_ids);
+    let Some(chat) = chat else {
+        // purging a chat removes its members and the chat itself in one go
+        return match payload.op.as_str() {
+            "DELETE" => Notification::new(
+                changed,
+                AppEvent::DeleteChat(DeletedChat {
+                    chat_id: payload.chat_id,
+                }),
+            )
+            .into_iter()
+            .collect(),
+            _ => vec![],
+        };
+    };
+    let others: HashSet<u64> = user_ids(&chat.members)
+        .difference(&changed)
+        .copied()
+        .collect();
+
+    let notifications = match payload.op.as_str() {
+        "INSERT" if payload.new_chat => vec![Notification::new(changed, AppEvent::NewChat(chat))],
+        "INSERT" => vec![
+            Notification::new(changed, AppEvent::AddToChat(chat.clone())),
+            Notification::new(others, AppEvent::UpdateChat(chat)),
+        ],
+        "DELETE" => vec![
+            Notification::new(changed, AppEvent::RemoveFromChat(chat.clone())),
+            Notification::new(others, AppEvent::UpdateChat(chat)),
+        ],
+        op => {
+            warn!("unexpected chat members operation: {}", op);
+            vec![]
+        }
+    };
+
+    notifications.into_iter().flatten().collect()
+}
+
+fn load_message_notification(op: &str, message: Message, members: &[i64]) -> Option<Notification> {
+    let ids = user_ids(members);
+    let event = match op {
+        "INSERT" => AppEvent::NewMessage(message),
+        // deleting a message leaves a tombstone behind
+        "UPDATE" if message.deleted_at.is_some() => AppEvent::DeleteMessage(message),
+        "UPDATE" => AppEvent::UpdateMessage(message),
+        op => {
+            warn!("unexpected message operation: {}", op);
+            return None;
+        }
+    };
+
+    Notification::new(ids, event)
+}
+
+fn load_reaction_notification(
+    op: &str,
+    reaction: ChatReaction,
+    members: &[i64],
+) -> Option<Notification> {
+    let ids = user_ids(members);
+    let event = match op {
+        "INSERT" => AppEvent::AddReaction(reaction),
+        "DELETE" => AppEvent::RemoveReaction(reaction),
+        op => {
+            warn!("unexpected reaction operation: {}", op);
+            return None;
+        }
+    };
+
+    Notification::new(ids, event)
+}
+
+fn load_mention_notification(mention: ChatMention) -> Vec<Notification> {
+    let ids = HashSet::from([mention.user_id as u64]);
+    Notification::new(ids, AppEvent::Mention(mention))
+        .into_iter()
+        .collect()
+}
+
+// the reader's own connections stay in sync, and members of small chats get a read receipt
+fn load_read_notification(read: ChatRead, chat: &Chat) -> Option<Notification> {
+    let ids = match chat.r#type {
+        ChatType::Single | ChatType::Group => user_ids(&chat.members),
+        _ => HashSet::from([read.user_id as u64]),
+    };
+    let event = AppEvent::MessageRead(MessageRead {
+        chat_id: read.chat_id,
+        user_id: read.user_id,
+        message_id: read.last_read_message_id,
+    });
+
+    Notification::new(ids, event)
+}
+
+fn user_ids(members: &[i64]) -> HashSet<u64> {
+    members.iter().map(|id| *id as u64).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAT: &str = r#"{"id": 1, "ws_id": 1, "name": "general", "type": "public_channel", "members": [1, 2, 3], "created_at": "2024-11-02T09:00:00.123456+00:00"}"#;
+
+    const MESSAGE: &str = r#"{"id": 1, "chat_id": 1, "sender_id": 2, "content": "hello", "files": [], "created_at": "2024-11-02T09:00:00.123456+00:00", "updated_at": "2024-11-02T09:00:00.123456+00:00"}"#;
+
+    #[test]
+    fn test_payloads_should_carry_ids_only() -> Result<()> {
+        let payload: ChatUpdated = serde_json::from_str(r#"{"op": "UPDATE", "chat_id": 1}"#)?;
+        assert_eq!((payload.op.as_str(), payload.chat_id), ("UPDATE", 1));
+
+        let payload: MessageUpdated =
+            serde_json::from_str(r#"{"op": "INSERT", "chat_id": 1, "message_id": 2}"#)?;
+        assert_eq!(payload.op, "INSERT");
+        assert_eq!((payload.chat_id, payload.message_id), (1, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_typing_and_presence_should_be_ephemeral() {
+        let typing = AppEvent::Typing(ChatTyping {
+            chat_id: 1,
+            user_id: 2,
+            typing: true,
+        });
+        let presence = AppEvent::Presence(UserPresence {
+            user_id: 2,
+            presence: Presence::Away,
+        });
+        assert!(typing.is_ephemeral());
+        assert!(presence.is_ephemeral());
+        assert!(!AppEvent::ResyncRequired.is_ephemeral());
+    }
+
+    #[test]
+    fn test_chat_update_should_notify_members() -> Result<()> {
+        let notification = load_chat_notification("UPDATE", serde_json::from_str(CHAT)?)
+            .expect("expect a notification");
+        assert_eq!(notification.user_ids, HashSet::from([1, 2, 3]));
+        assert_eq!(notification.event.name(), "UpdateChat");
+        Ok(())
+    }
+
+    #[test]
+    fn test_chat_members_should_notify_joined_left_and_others() -> Result<()> {
+        let chat = || serde_json::from_str::<Chat>(CHAT);
+
+        let payload = r#"{"op": "INSERT", "chat_id": 1, "user_ids": [1, 2, 3], "new_chat": true}"#;
+        let notifications =
+            load_chat_members_notifications(serde_json::from_str(payload)?, Some(chat()?));
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2, 3]));
+        assert_eq!(notifications[0].event.name(), "NewChat");
+
+        // the chunks of a new chat each notify their own users
+        let payload = r#"{"op": "INSERT", "chat_id": 1, "user_ids": [3], "new_chat": true}"#;
+        let notifications =
+            load_chat_members_notifications(serde_json::from_str(payload)?, Some(chat()?));
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].user_ids, HashSet::from([3]));
+        assert_eq!(notifications[0].event.name(), "NewChat");
+
+        let payload = r#"{"op": "INSERT", "chat_id": 1, "user_ids": [3], "new_chat": false}"#;
+        let notifications =
+            load_chat_members_notifications(serde_json::from_str(payload)?, Some(chat()?));
+        assert_eq!(notifications[0].user_ids, HashSet::from([3]));
+        assert_eq!(notifications[0].event.name(), "AddToChat");
+        assert_eq!(notifications[1].user_ids, HashSet::from([1, 2]));
+        assert_eq!(notifications[1].event.name(), "UpdateChat");
+
+        let payload = r#"{"op": "DELETE", "chat_id": 1, "user_ids": [4], "new_chat": false}"#;
+        let notifications =
+            load_chat_members_notifications(serde_json::from_str(payload)?, Some(chat()?));
+        assert_eq!(notifications[0].user_ids, HashSet::from([4]));
+        assert_eq!(notifications[0].event.name(), "RemoveFromChat");
+        assert_eq!(notifications[1].user_ids, HashSet::from([1, 2, 3]));
+        assert_eq!(notifications[1].event.name(), "UpdateChat");
+        Ok(())
+    }
+
+    #[test]
+    fn test_purged_chat_should_notify_delete() -> Result<()> {
+        let payload = r#"{"op": "DELETE", "chat_id": 1, "user_ids": [1, 2], "new_chat": false}"#;
+        let notifications = load_chat_members_notifications(serde_json::from_str(payload)?, None);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2]));
+        assert_eq!(
+            notifications[0].event.as_ref(),
+            &AppEvent::DeleteChat(DeletedChat { chat_id: 1 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_message_should_notify_chat_members() -> Result<()> {
+        let notification =
+            load_message_notification("INSERT", serde_json::from_str(MESSAGE)?, &[2, 3])
+                .expect("expect a notification");
+        assert_eq!(notification.user_ids, HashSet::from([2, 3]));
+
+        let AppEvent::NewMessage(msg) = notification.event.as_ref() else {
+            panic!("expect NewMessage event");
+        };
+        assert_eq!(msg.content, "hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_tombstone_should_notify_delete() -> Result<()> {
+        let notification =
+            load_message_notification("UPDATE", serde_json::from_str(MESSAGE)?, &[2])
+                .expect("expect a notification");
+        assert_eq!(notification.event.name(), "UpdateMessage");
+
+        let message = MESSAGE.replace(
+            r#""content": "hello""#,
+            r#""content": "", "deleted_at": "2024-11-02T09:01:00.123456+00:00""#,
+        );
+        let notification =
+            load_message_notification("UPDATE", serde_json::from_str(&message)?, &[2])
+                .expect("expect a notification");
+        assert_eq!(notification.event.name(), "DeleteMessage");
+        Ok(())
+    }
+
+    #[test]
+    fn test_reaction_should_notify_chat_members() -> Result<()> {
+        let payload = r#"{"op": "DELETE",
+            "reaction": {"chat_id": 1, "message_id": 2, "user_id": 3, "emoji": "👍"}}"#;
+        let payload: ReactionUpdated = serde_json::from_str(payload)?;
+        let notifications: Vec<_> =
+            load_reaction_notification(&payload.op, payload.reaction, &[3, 4])
+                .into_iter()
+                .collect();
+        assert_eq!(notifications[0].user_ids, HashSet::from([3, 4]));
+
+        let AppEvent::RemoveReaction(reaction) = notifications[0].event.as_ref() else {
+            panic!("expect RemoveReaction event");
+        };
+        assert_eq!(reaction.emoji, "👍");
+        Ok(())
+    }
+
+    #[test]
+    fn test_mention_should_notify_mentioned_user() -> Result<()> {
+        let payload = r#"{"mention": {"chat_id": 1, "message_id": 2, "sender_id": 3, "user_id": 4, "kind": "here"}}"#;
+        let payload: MentionCreated = serde_json::from_str(payload)?;
+        let notifications = load_mention_notification(payload.mention);
+        assert_eq!(notifications[0].user_ids, HashSet::from([4]));
+
+        let AppEvent::Mention(mention) = notifications[0].event.as_ref() else {
+            panic!("expect Mention event");
+        };
+        assert_eq!(mention.kind, MentionKind::Here);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_should_notify_members_of_small_chats_only() -> Result<()> {
+        let payload = r#"{"read": {"chat_id": 1, "user_id": 2, "last_read_message_id": 10, "updated_at": "2024-11-02T09:00:00.123456+00:00"}}"#;
+        let payload: ChatReadUpdated = serde_json::from_str(payload)?;
+        let group = serde_json::from_str(&CHAT.replace("public_channel", "group"))?;
+        let notifications: Vec<_> = load_read_notification(payload.read.clone(), &group)
+            .into_iter()
+            .collect();
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2, 3]));
+        assert_eq!(notifications[0].event.name(), "MessageRead");
+
+        let notifications: Vec<_> =
+            load_read_notification(payload.read, &serde_json::from_str(CHAT)?)
+                .into_iter()
+                .collect();
+        assert_eq!(notifications[0].user_ids, HashSet::from([2]));
+        Ok(())
+    }
+}
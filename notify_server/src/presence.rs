@@ -0,0 +1,91 @@
+use std::{collections::HashSet, sync::Arc};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use chat_core::{Presence, User};
+
+use crate::{error::AppError, notif::UserPresence, AppEvent, AppState};
+
+impl AppState {
+    pub(crate) async fn heartbeat(&self, user_id: u64) -> Result<(), AppError> {
+        if let Some(mut ch) = self.users.get_mut(&user_id) {
+            ch.heartbeat();
+        }
+        self.refresh_presence(user_id).await
+    }
+
+    /// Re-evaluate the presence of the user, publish it if it changed.
+    pub(crate) async fn refresh_presence(&self, user_id: u64) -> Result<(), AppError> {
+        // the entry must be released before publish_presence iterates the users
+        let changed = self.users.get_mut(&user_id).and_then(|mut ch| {
+            let presence = ch.update_presence()?;
+            Some((ch.ws_id, presence))
+        });
+
+        if let Some((ws_id, presence)) = changed {
+            self.publish_presence(user_id, ws_id, presence).await?;
+        }
+        Ok(())
+    }
+
+    /// Catch the users who disconnected or went idle.
+    pub(crate) async fn refresh_all_presences(&self) -> Result<(), AppError> {
+        let changes: Vec<_> = self
+            .users
+            .iter_mut()
+            .filter_map(|mut ch| {
+                let presence = ch.update_presence()?;
+                Some((*ch.key(), ch.ws_id, presence))
+            })
+            .collect();
+
+        for (user_id, ws_id, presence) in changes {
+            self.publish_presence(user_id, ws_id, presence).await?;
+        }
+        Ok(())
+    }
+
+    // notify_server owns the presence column, nobody is connected when it starts
+    pub(crate) async fn reset_presences(&self) -> Result<(), AppError> {
+        sqlx::query("UPDATE users SET presence='offline' WHERE presence <> 'offline'")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn publish_presence(
+        &self,
+        user_id: u64,
+        ws_id: i64,
+        presence: Presence,
+    ) -> Result<(), AppError> {
+        sqlx::query("UPDATE users SET presence=$1 WHERE id=$2")
+            .bind(presence)
+            .bind(user_id as i64)
+            .execute(&self.pool)
+            .await?;
+
+        // only the connected users of the workspace need to know
+        let user_ids: HashSet<u64> = self
+            .users
+            .iter()
+            .filter(|ch| ch.ws_id == ws_id)
+            .map(|ch| *ch.key())
+            .collect();
+
+        let event = AppEvent::Presence(UserPresence {
+            user_id: user_id as i64,
+            presence,
+        });
+        self.notify(&user_ids, Arc::new(event));
+        Ok(())
+    }
+}
+
+// SSE clients can't send anything over their connection, they heartbeat here
+pub(crate) async fn heartbeat_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    state.heartbeat(user.id as u64).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,23 +1,65 @@
-use std::{convert::Infallible, time::Duration};
+use std::{convert::Infallible, sync::Arc, time::Duration};
 
-use axum::response::sse::{Event, Sse};
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
 use axum_extra::{headers, TypedHeader};
-use futures::{stream, Stream};
+use chat_core::User;
+use futures::Stream;
 use tokio_stream::StreamExt;
-use tracing::info;
+use tracing::{info, warn};
+
+use crate::{channel::UserEvent, AppState};
+
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
 
 pub(crate) async fn sse_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
     TypedHeader(user_agent): TypedHeader<headers::UserAgent>,
+    headers: HeaderMap,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    info!("{} connected", user_agent.as_str());
+    let user_id = user.id as u64;
+    let last_event_id = headers
+        .get(LAST_EVENT_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    info!(
+        "user {} connected: {}, last event id: {:?}",
+        user_id,
+        user_agent.as_str(),
+        last_event_id
+    );
 
-    let stream = stream::repeat_with(|| Event::default().data("Hello!"))
-        .map(Ok)
-        .throttle(Duration::from_secs(1));
+    let stream = state
+        .event_stream(&user, last_event_id)
+        .filter_map(to_sse_event);
+    if let Err(e) = state.refresh_presence(user_id).await {
+        warn!("refresh presence of user {} failed: {}", user_id, e);
+    }
 
     Sse::new(stream).keep_alive(
-        axum::response::sse::KeepAlive::new()
+        KeepAlive::new()
             .interval(Duration::from_secs(1))
             .text("keep-alive-text"),
     )
 }
+
+fn to_sse_event(v: Arc<UserEvent>) -> Option<Result<Event, Infallible>> {
+    match serde_json::to_string(v.event.as_ref()) {
+        Ok(data) => {
+            let event = Event::default().event(v.event.name()).data(data);
+            match v.id {
+                Some(id) => Some(Ok(event.id(id.to_string()))),
+                None => Some(Ok(event)),
+            }
+        }
+        Err(e) => {
+            warn!("serialize event failed: {}", e);
+            None
+        }
+    }
+}
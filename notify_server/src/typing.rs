@@ -0,0 +1,235 @@
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chat_core::User;
+use dashmap::DashMap;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{error::AppError, notif::ChatTyping, AppEvent, AppState};
+
+// a client has to repeat the typing signal before this, or it's considered stopped
+const TYPING_TTL: Duration = Duration::from_secs(6);
+const TYPING_EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TypingInput {
+    typing: bool,
+}
+
+#[derive(Debug)]
+struct Typing {
+    // the other members of the chat, who see the indicator
+    others: HashSet<u64>,
+    expires_at: Instant,
+}
+
+/// Who is typing in which chat, keyed by (chat_id, user_id).
+#[derive(Debug, Default)]
+pub(crate) struct TypingTracker {
+    typing: DashMap<(u64, u64), Typing>,
+}
+
+impl TypingTracker {
+    /// Extend the indicator if the user is already typing in the chat.
+    fn refresh(&self, chat_id: u64, user_id: u64, now: Instant) -> bool {
+        match self.typing.get_mut(&(chat_id, user_id)) {
+            Some(mut t) => {
+                t.expires_at = now + TYPING_TTL;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if the user was not typing in the chat yet.
+    fn start(&self, chat_id: u64, user_id: u64, others: HashSet<u64>, now: Instant) -> bool {
+        let typing = Typing {
+            others,
+            expires_at: now + TYPING_TTL,
+        };
+        self.typing.insert((chat_id, user_id), typing).is_none()
+    }
+
+    fn stop(&self, chat_id: u64, user_id: u64) -> Option<HashSet<u64>> {
+        self.typing
+            .remove(&(chat_id, user_id))
+            .map(|(_, t)| t.others)
+    }
+
+    fn stop_user(&self, user_id: u64) -> Vec<(u64, HashSet<u64>)> {
+        let chat_ids: Vec<u64> = self
+            .typing
+            .iter()
+            .filter(|t| t.key().1 == user_id)
+            .map(|t| t.key().0)
+            .collect();
+
+        chat_ids
+            .into_iter()
+            .filter_map(|chat_id| Some((chat_id, self.stop(chat_id, user_id)?)))
+            .collect()
+    }
+
+    fn expire(&self, now: Instant) -> Vec<(u64, u64, HashSet<u64>)> {
+        let keys: Vec<(u64, u64)> = self
+            .typing
+            .iter()
+            .filter(|t| t.expires_at <= now)
+            .map(|t| *t.key())
+            .collect();
+
+        // the indicator may have been refreshed in between
+        keys.into_iter()
+            .filter_map(|key| self.typing.remove_if(&key, |_, t| t.expires_at <= now))
+            .map(|((chat_id, user_id), t)| (chat_id, user_id, t.others))
+            .collect()
+    }
+}
+
+impl AppState {
+    pub(crate) async fn set_typing(
+        &self,
+        user_id: u64,
+        chat_id: u64,
+        typing: bool,
+    ) -> Result<(), AppError> {
+        if !typing {
+            if let Some(others) = self.typing.stop(chat_id, user_id) {
+                self.notify_typing(&others, chat_id, user_id, false);
+            }
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        if self.typing.refresh(chat_id, user_id, now) {
+            return Ok(());
+        }
+
+        let others: HashSet<u64> = self
+            .get_chat_members(chat_id, user_id)
+            .await?
+            .into_iter()
+            .map(|id| id as u64)
+            .filter(|id| *id != user_id)
+            .collect();
+
+        if self.typing.start(chat_id, user_id, others.clone(), now) {
+            self.notify_typing(&others, chat_id, user_id, true);
+        }
+        Ok(())
+    }
+
+    /// Clear the indicators of a user whose last connection is gone. While another device
+    /// is connected, the indicators stay until they are stopped or expire.
+    pub(crate) fn stop_typing(&self, user_id: u64) {
+        if self.users.get(&user_id).is_some_and(|ch| ch.is_connected()) {
+            return;
+        }
+        for (chat_id, others) in self.typing.stop_user(user_id) {
+            self.notify_typing(&others, chat_id, user_id, false);
+        }
+    }
+
+    fn expire_typing(&self) {
+        for (chat_id, user_id, others) in self.typing.expire(Instant::now()) {
+            info!("typing of user {} in chat {} expired", user_id, chat_id);
+            self.notify_typing(&others, chat_id, user_id, false);
+        }
+    }
+
+    fn notify_typing(&self, others: &HashSet<u64>, chat_id: u64, user_id: u64, typing: bool) {
+        let event = AppEvent::Typing(ChatTyping {
+            chat_id: chat_id as i64,
+            user_id: user_id as i64,
+            typing,
+        });
+        self.notify(others, Arc::new(event));
+    }
+}
+
+pub(crate) fn setup_typing_expiry(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TYPING_EXPIRY_INTERVAL);
+        loop {
+            interval.tick().await;
+            state.expire_typing();
+        }
+    });
+}
+
+// SSE clients can't send anything over their connection, they signal typing here
+pub(crate) async fn typing_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(chat_id): Path<u64>,
+    Json(input): Json<TypingInput>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .set_typing(user.id as u64, chat_id, input.typing)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typing_should_start_once_and_stop() {
+        let tracker = TypingTracker::default();
+        let now = Instant::now();
+
+        assert!(!tracker.refresh(1, 1, now));
+        assert!(tracker.start(1, 1, HashSet::from([2, 3]), now));
+        assert!(tracker.refresh(1, 1, now));
+
+        assert_eq!(tracker.stop(1, 1), Some(HashSet::from([2, 3])));
+        assert_eq!(tracker.stop(1, 1), None);
+    }
+
+    #[test]
+    fn test_typing_should_expire() {
+        let tracker = TypingTracker::default();
+        let now = Instant::now();
+        tracker.start(1, 1, HashSet::from([2]), now);
+        tracker.start(2, 1, HashSet::from([3]), now + Duration::from_secs(3));
+
+        assert!(tracker.expire(now + Duration::from_secs(1)).is_empty());
+
+        let expired = tracker.expire(now + TYPING_TTL);
+        assert_eq!(expired, vec![(1, 1, HashSet::from([2]))]);
+
+        // refreshed before it expired
+        tracker.refresh(2, 1, now + TYPING_TTL);
+        assert!(tracker
+            .expire(now + TYPING_TTL + Duration::from_secs(3))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_typing_should_stop_all_chats_of_user() {
+        let tracker = TypingTracker::default();
+        let now = Instant::now();
+        tracker.start(1, 1, HashSet::from([2]), now);
+        tracker.start(2, 1, HashSet::from([3]), now);
+        tracker.start(2, 2, HashSet::from([1]), now);
+
+        let mut stopped = tracker.stop_user(1);
+        stopped.sort_by_key(|(chat_id, _)| *chat_id);
+        assert_eq!(
+            stopped,
+            vec![(1, HashSet::from([2])), (2, HashSet::from([3]))]
+        );
+        assert!(tracker.refresh(2, 2, now));
+    }
+}
@@ -0,0 +1,239 @@
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::IntoResponse,
+    Extension,
+};
+use chat_core::User;
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{error::AppError, AppEvent, AppState};
+
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+const PONG_TIMEOUT: Duration = Duration::from_secs(45);
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct WsParams {
+    // same as the Last-Event-ID header of /events, browsers can't set headers on WebSocket
+    last_event_id: Option<u64>,
+}
+
+/// Frames sent by the client, e.g. `{"type": "typing", "chat_id": 1, "typing": true}`.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ClientFrame {
+    /// The user started or stopped typing in a chat.
+    Typing { chat_id: u64, typing: bool },
+    /// The user is still active on this connection.
+    Heartbeat,
+    /// The user has read a chat up to a message.
+    Read { chat_id: u64, message_id: u64 },
+}
+
+/// Frames sent by the server, e.g. `{"type": "event", "id": 1, "data": {"event": "NewMessage", ...}}`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ServerFrame<'a> {
+    /// An event of the `/events` stream, ephemeral events have no id.
+    Event {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<u64>,
+        data: &'a AppEvent,
+    },
+    HeartbeatAck,
+    Error {
+        message: String,
+    },
+}
+
+pub(crate) async fn ws_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(params): Query<WsParams>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    info!(
+        "user {} connected over websocket, last event id: {:?}",
+        user.id, params.last_event_id
+    );
+    ws.on_upgrade(move |socket| handle_socket(socket, state, user, params.last_event_id))
+}
+
+async fn handle_socket(socket: WebSocket, state: AppState, user: User, last_event_id: Option<u64>) {
+    let user_id = user.id as u64;
+    let (mut sender, mut receiver) = socket.split();
+
+    let mut events = Box::pin(state.event_stream(&user, last_event_id));
+    if let Err(e) = state.refresh_presence(user_id).await {
+        warn!("refresh presence of user {} failed: {}", user_id, e);
+    }
+
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    let mut last_pong = Instant::now();
+
+    loop {
+        let reply = tokio::select! {
+            event = events.next() => match event {
+                Some(v) => to_message(&ServerFrame::Event { id: v.id, data: &v.event }),
+                // lagged behind, the client will reconnect with last_event_id
+                None => break,
+            },
+            msg = receiver.next() => match msg {
+                Some(Ok(Message::Text(text))) => state.handle_text(&user, &text).await,
+                Some(Ok(Message::Binary(_))) => to_message(&ServerFrame::Error {
+                    message: "binary frames are not supported".to_string(),
+                }),
+                Some(Ok(Message::Pong(_))) => {
+                    last_pong = Instant::now();
+                    None
+                }
+                // pings are answered by the websocket library
+                Some(Ok(Message::Ping(_))) => None,
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Err(e)) => {
+                    warn!("user {} websocket error: {}", user_id, e);
+                    break;
+                }
+            },
+            _ = ping.tick() => {
+                if last_pong.elapsed() > PONG_TIMEOUT {
+                    info!("user {} websocket timed out", user_id);
+                    break;
+                }
+                Some(Message::Ping(vec![]))
+            }
+        };
+
+        if let Some(msg) = reply {
+            if let Err(e) = sender.send(msg).await {
+                warn!("user {} websocket send failed: {}", user_id, e);
+                break;
+            }
+        }
+    }
+
+    info!("user {} websocket disconnected", user_id);
+    // the stream holds this connection's subscription, drop it before counting the others
+    drop(events);
+    state.stop_typing(user_id);
+    if let Err(e) = state.refresh_presence(user_id).await {
+        warn!("refresh presence of user {} failed: {}", user_id, e);
+    }
+}
+
+impl AppState {
+    async fn handle_text(&self, user: &User, text: &str) -> Option<Message> {
+        let frame = match serde_json::from_str::<ClientFrame>(text) {
+            Ok(frame) => frame,
+            Err(e) => {
+                return to_message(&ServerFrame::Error {
+                    message: format!("invalid frame: {}", e),
+                })
+            }
+        };
+
+        match self.handle_client_frame(user, frame).await {
+            Ok(Some(frame)) => to_message(&frame),
+            Ok(None) => None,
+            Err(e) => to_message(&ServerFrame::Error {
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    async fn handle_client_frame(
+        &self,
+        user: &User,
+        frame: ClientFrame,
+    ) -> Result<Option<ServerFrame<'static>>, AppError> {
+        let user_id = user.id as u64;
+        match frame {
+            ClientFrame::Heartbeat => {
+                self.heartbeat(user_id).await?;
+                Ok(Some(ServerFrame::HeartbeatAck))
+            }
+            ClientFrame::Typing { chat_id, typing } => {
+                self.set_typing(user_id, chat_id, typing).await?;
+                Ok(None)
+            }
+            ClientFrame::Read {
+                chat_id,
+                message_id,
+            } => {
+                // the MessageRead event comes back through the chat_read_updated notification
+                self.mark_read(chat_id, user_id, message_id).await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn to_message(frame: &ServerFrame) -> Option<Message> {
+    match serde_json::to_string(frame) {
+        Ok(text) => Some(Message::Text(text)),
+        Err(e) => {
+            warn!("serialize frame failed: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_frame_should_parse() -> anyhow::Result<()> {
+        let frame: ClientFrame =
+            serde_json::from_str(r#"{"type": "typing", "chat_id": 1, "typing": true}"#)?;
+        assert_eq!(
+            frame,
+            ClientFrame::Typing {
+                chat_id: 1,
+                typing: true
+            }
+        );
+
+        let frame: ClientFrame = serde_json::from_str(r#"{"type": "heartbeat"}"#)?;
+        assert_eq!(frame, ClientFrame::Heartbeat);
+
+        let frame: ClientFrame =
+            serde_json::from_str(r#"{"type": "read", "chat_id": 1, "message_id": 10}"#)?;
+        assert_eq!(
+            frame,
+            ClientFrame::Read {
+                chat_id: 1,
+                message_id: 10
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_server_frame_should_serialize() -> anyhow::Result<()> {
+        let event = AppEvent::ResyncRequired;
+        let frame = ServerFrame::Event {
+            id: Some(1),
+            data: &event,
+        };
+        assert_eq!(
+            serde_json::to_string(&frame)?,
+            r#"{"type":"event","id":1,"data":{"event":"ResyncRequired"}}"#
+        );
+
+        let frame = ServerFrame::Error {
+            message: "oops".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&frame)?,
+            r#"{"type":"error","message":"oops"}"#
+        );
+        Ok(())
+    }
+}